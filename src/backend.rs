@@ -0,0 +1,675 @@
+/*
+ * Copyright 2025  Simon Arlott
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use std::{
+	env, fmt,
+	process::{Child, Command},
+	sync::{Mutex, OnceLock},
+	thread,
+	time::Duration,
+};
+
+use anyhow::{Error, anyhow};
+use libxdo::{Search, Window, XDo};
+use log::{error, trace, warn};
+use sha2::{Digest, Sha256};
+use xcap::Monitor;
+
+use crate::devtools::DevTools;
+use crate::output::Browser;
+use crate::wayland::{Grim, Keyboard};
+
+/// Type of graphical session the browser runs in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Session {
+	X11,
+	Wayland,
+}
+
+/// Control of the browser and capture of the screen
+///
+/// Tabs are numbered from [`Browser::FIRST_TAB`]. The default
+/// implementations use keyboard shortcuts on the current tab.
+pub trait Backend: fmt::Debug + Send + Sync {
+	/// Start the browser with a tab for each url
+	fn launch(&self, urls: &[String]) -> Result<(), Error>;
+
+	/// Wait for the browser to stop
+	fn wait(&self) -> Result<(), Error>;
+
+	/// Press keys on the current tab (in xdotool format)
+	fn press(&self, tab: usize, keys: &str) -> Result<(), Error>;
+
+	/// Capture the screen and return a hash of its content
+	fn capture(&self) -> Option<String>;
+
+	/// Read the url that the current tab is actually showing, if possible
+	fn active_url(&self, _tab: usize) -> Option<String> {
+		None
+	}
+
+	/// Switch from the current tab to another tab
+	fn switch_tab(&self, current: usize, tab: usize, count: usize) -> Result<(), Error> {
+		let keys = tab_keys(current, tab, count)?;
+
+		if keys.is_empty() {
+			/* Nothing to do */
+			Ok(())
+		} else {
+			self.press(current, &keys.join(" "))
+		}
+	}
+
+	fn reload(&self, tab: usize) -> Result<(), Error> {
+		self.press(tab, "Ctrl+r")
+	}
+
+	/// Close the current tab, making the next tab (or the last tab) current
+	fn close_tab(&self, tab: usize) -> Result<(), Error> {
+		self.press(tab, "Ctrl+w")
+	}
+
+	/// Navigate the current tab to another url
+	fn navigate(&self, tab: usize, url: &str) -> Result<(), Error> {
+		self.press(tab, "Ctrl+l")?;
+		self.press(tab, &keysyms(url))?;
+		self.press(tab, "Return")
+	}
+
+	/// Open a new tab at the end and make it current
+	fn open_tab(&self, current: usize, url: &str) -> Result<(), Error> {
+		/* Wait for the location bar of a new tab */
+		const NEW_TAB_DELAY: Duration = Duration::from_millis(500);
+
+		self.press(current, "Ctrl+t")?;
+		thread::sleep(NEW_TAB_DELAY);
+		self.press(current, &keysyms(url))?;
+		self.press(current, "Return")
+	}
+
+	/// Move the current tab to an earlier position
+	fn move_tab(&self, from: usize, to: usize) -> Result<(), Error> {
+		self.press(from, &vec!["Ctrl+Shift+Prior"; from - to].join(" "))
+	}
+}
+
+/// Chromium on X11, controlled with libxdo or xdotool
+#[derive(Debug)]
+pub struct X11 {
+	kiosk: bool,
+	hands: Mutex<Hands>,
+	eyes: Eyes,
+	child: Mutex<Option<Child>>,
+}
+
+/// Chromium on Wayland, controlled with a uinput virtual keyboard
+#[derive(Debug)]
+pub struct Wayland {
+	kiosk: bool,
+	keyboard: Mutex<Keyboard>,
+	eyes: Eyes,
+	child: Mutex<Option<Child>>,
+}
+
+/// Chromium controlled using the DevTools protocol
+#[derive(Debug)]
+pub struct Cdp {
+	session: Session,
+	kiosk: bool,
+	port: u16,
+	devtools: OnceLock<DevTools>,
+	eyes: Eyes,
+	child: Mutex<Option<Child>>,
+}
+
+#[derive(derive_more::Debug)]
+struct Hands {
+	no_search: bool,
+	use_xdotool: bool,
+	script: String,
+	#[debug("XDo")]
+	xdo: XDo,
+	window: Option<Window>,
+}
+
+#[derive(derive_more::Debug)]
+enum Eyes {
+	Xcap(Vec<Monitor>),
+	Grim(Grim),
+}
+
+fn chromium(session: Session, kiosk: bool) -> Command {
+	let mut command = Command::new("chromium-browser");
+
+	if kiosk {
+		command.arg("--kiosk");
+	}
+	if session == Session::Wayland {
+		command.arg("--ozone-platform=wayland");
+	}
+	command.arg("--disable-web-security").arg("--temp-profile");
+	command
+}
+
+fn wait(child: &Mutex<Option<Child>>) -> Result<(), Error> {
+	let child = child.lock().unwrap().take();
+
+	child.ok_or(anyhow!("Browser not started"))?.wait()?;
+	Ok(())
+}
+
+/// Plan the key presses that switch from the current tab to another tab
+pub fn tab_keys(current: usize, tab: usize, count: usize) -> Result<Vec<String>, Error> {
+	const KEY_NEXT_TAB: &str = "Ctrl+Tab";
+	const KEY_PREVIOUS_TAB: &str = "Ctrl+Shift+Tab";
+	const DIRECT_TABS: usize = 8;
+	const KEY_LAST_TAB: &str = "Ctrl+9";
+	let key_direct_tab = |tab: usize| "Ctrl+".to_owned() + &tab.to_string();
+	let last = count + Browser::FIRST_TAB - 1;
+	let tabs = Browser::FIRST_TAB..=last;
+
+	if !tabs.contains(&current) {
+		return Err(anyhow!(
+			"Unable to navigate from invalid tab {current} of {count}"
+		));
+	} else if !tabs.contains(&tab) {
+		return Err(anyhow!(
+			"Unable to navigate to invalid tab {tab} of {count} from tab {current}"
+		));
+	}
+
+	let previous = if current == Browser::FIRST_TAB {
+		last
+	} else {
+		current - 1
+	};
+	let next = if current == last {
+		Browser::FIRST_TAB
+	} else {
+		current + 1
+	};
+	let direct_tabs = Browser::FIRST_TAB..=DIRECT_TABS;
+
+	Ok(if tab == current {
+		vec![]
+	} else if direct_tabs.contains(&tab) {
+		vec![key_direct_tab(tab)]
+	} else if tab == last {
+		vec![KEY_LAST_TAB.to_owned()]
+	} else if tab == next {
+		vec![KEY_NEXT_TAB.to_owned()]
+	} else if tab == previous {
+		vec![KEY_PREVIOUS_TAB.to_owned()]
+	} else {
+		/* Between the last direct tab and the last tab */
+		let (keys_from_current_tab, key_from_current_tab) = if tab < current {
+			(current - tab, KEY_PREVIOUS_TAB)
+		} else {
+			(tab - current, KEY_NEXT_TAB)
+		};
+		let keys_from_last_direct_tab = 1 + (tab - DIRECT_TABS);
+		let keys_from_last_tab = 1 + (last - tab);
+		let mut keys = Vec::new();
+
+		if keys_from_current_tab <= keys_from_last_direct_tab.min(keys_from_last_tab) {
+			/* Navigate from current tab */
+			keys.resize(keys_from_current_tab, key_from_current_tab.to_owned());
+		} else if keys_from_last_direct_tab <= keys_from_last_tab {
+			/* Navigate from last direct tab */
+			keys.push(key_direct_tab(DIRECT_TABS));
+			keys.resize(keys_from_last_direct_tab, KEY_NEXT_TAB.to_owned());
+		} else {
+			/* Navigate from last tab */
+			keys.push(KEY_LAST_TAB.to_owned());
+			keys.resize(keys_from_last_tab, KEY_PREVIOUS_TAB.to_owned());
+		}
+
+		keys
+	})
+}
+
+/// Convert text into a sequence of key names that will type it
+fn keysyms(text: &str) -> String {
+	text.chars()
+		.map(|c| match c {
+			'a'..='z' | 'A'..='Z' | '0'..='9' => c.to_string(),
+			':' => "colon".to_owned(),
+			'/' => "slash".to_owned(),
+			'.' => "period".to_owned(),
+			'-' => "minus".to_owned(),
+			'_' => "underscore".to_owned(),
+			'#' => "numbersign".to_owned(),
+			'?' => "question".to_owned(),
+			'=' => "equal".to_owned(),
+			'&' => "ampersand".to_owned(),
+			'%' => "percent".to_owned(),
+			'+' => "plus".to_owned(),
+			'~' => "asciitilde".to_owned(),
+			'@' => "at".to_owned(),
+			',' => "comma".to_owned(),
+			';' => "semicolon".to_owned(),
+			'!' => "exclam".to_owned(),
+			_ => format!("U{:04X}", c as u32),
+		})
+		.collect::<Vec<_>>()
+		.join(" ")
+}
+
+impl Session {
+	/// Determine the session type from the environment
+	pub fn detect() -> Self {
+		if env::var("XDG_SESSION_TYPE").is_ok_and(|value| value == "wayland")
+			|| env::var_os("WAYLAND_DISPLAY").is_some()
+		{
+			Self::Wayland
+		} else {
+			Self::X11
+		}
+	}
+}
+
+impl X11 {
+	pub fn new(kiosk: bool, use_xdotool: bool, no_search: bool) -> Self {
+		Self {
+			kiosk,
+			hands: Mutex::new(Hands::new(use_xdotool, no_search)),
+			eyes: Eyes::new(Session::X11),
+			child: Mutex::new(None),
+		}
+	}
+}
+
+impl Backend for X11 {
+	fn launch(&self, urls: &[String]) -> Result<(), Error> {
+		*self.child.lock().unwrap() = Some(chromium(Session::X11, self.kiosk).args(urls).spawn()?);
+		Ok(())
+	}
+
+	fn wait(&self) -> Result<(), Error> {
+		wait(&self.child)
+	}
+
+	fn press(&self, _tab: usize, keys: &str) -> Result<(), Error> {
+		self.hands.lock().unwrap().press(keys)
+	}
+
+	fn capture(&self) -> Option<String> {
+		self.eyes.see()
+	}
+}
+
+impl Wayland {
+	pub fn new(kiosk: bool) -> Self {
+		Self {
+			kiosk,
+			keyboard: Mutex::new(Keyboard::new()),
+			eyes: Eyes::new(Session::Wayland),
+			child: Mutex::new(None),
+		}
+	}
+}
+
+impl Backend for Wayland {
+	fn launch(&self, urls: &[String]) -> Result<(), Error> {
+		*self.child.lock().unwrap() =
+			Some(chromium(Session::Wayland, self.kiosk).args(urls).spawn()?);
+		Ok(())
+	}
+
+	fn wait(&self) -> Result<(), Error> {
+		wait(&self.child)
+	}
+
+	fn press(&self, _tab: usize, keys: &str) -> Result<(), Error> {
+		self.keyboard.lock().unwrap().press(keys)
+	}
+
+	fn capture(&self) -> Option<String> {
+		self.eyes.see()
+	}
+}
+
+impl Cdp {
+	pub fn new(session: Session, kiosk: bool, port: u16) -> Self {
+		Self {
+			session,
+			kiosk,
+			port,
+			devtools: OnceLock::new(),
+			eyes: Eyes::new(session),
+			child: Mutex::new(None),
+		}
+	}
+
+	fn devtools(&self) -> Result<&DevTools, Error> {
+		self.devtools.get().ok_or(anyhow!("Browser not started"))
+	}
+}
+
+impl Backend for Cdp {
+	fn launch(&self, urls: &[String]) -> Result<(), Error> {
+		let (child, devtools) = DevTools::launch(chromium(self.session, self.kiosk), self.port)?;
+
+		*self.child.lock().unwrap() = Some(child);
+		devtools.open_all(urls)?;
+		self.devtools
+			.set(devtools)
+			.map_err(|_| anyhow!("Browser already started"))
+	}
+
+	fn wait(&self) -> Result<(), Error> {
+		wait(&self.child)
+	}
+
+	fn press(&self, tab: usize, keys: &str) -> Result<(), Error> {
+		self.devtools()?.press(tab - Browser::FIRST_TAB, keys)
+	}
+
+	fn capture(&self) -> Option<String> {
+		self.eyes.see()
+	}
+
+	fn active_url(&self, tab: usize) -> Option<String> {
+		self.devtools()
+			.ok()?
+			.url(tab - Browser::FIRST_TAB)
+			.inspect_err(|err| warn!("Unable to get url of tab {tab}: {err}"))
+			.ok()
+	}
+
+	fn switch_tab(&self, _current: usize, tab: usize, _count: usize) -> Result<(), Error> {
+		self.devtools()?.activate(tab - Browser::FIRST_TAB)
+	}
+
+	fn reload(&self, tab: usize) -> Result<(), Error> {
+		self.devtools()?.reload(tab - Browser::FIRST_TAB)
+	}
+
+	fn close_tab(&self, tab: usize) -> Result<(), Error> {
+		let devtools = self.devtools()?;
+
+		let index = tab - Browser::FIRST_TAB;
+
+		devtools.close(index)?;
+
+		/* Make the same tab current as closing it with the keyboard would */
+		if devtools.activate(index).is_err() && index > 0 {
+			devtools.activate(index - 1)?;
+		}
+		Ok(())
+	}
+
+	fn navigate(&self, tab: usize, url: &str) -> Result<(), Error> {
+		self.devtools()?.navigate(tab - Browser::FIRST_TAB, url)
+	}
+
+	fn open_tab(&self, _current: usize, url: &str) -> Result<(), Error> {
+		self.devtools()?.open(url)
+	}
+
+	fn move_tab(&self, from: usize, to: usize) -> Result<(), Error> {
+		/* Tabs are activated by id so the order of the tab strip doesn't matter */
+		self.devtools()?
+			.move_tab(from - Browser::FIRST_TAB, to - Browser::FIRST_TAB)
+	}
+}
+
+impl Hands {
+	const BROWSER_WINDOW_CLASS_REGEX: &str = "^chromium(-browser)?$";
+
+	pub fn new(use_xdotool: bool, no_search: bool) -> Self {
+		let mut script = "xdotool key ".to_string();
+
+		if !no_search {
+			script += "--window $(xdotool search --onlyvisible --class '";
+			script += Self::BROWSER_WINDOW_CLASS_REGEX;
+			script += "') ";
+		}
+
+		Self {
+			use_xdotool,
+			script,
+			xdo: XDo::new(None).unwrap(),
+			no_search,
+			window: None,
+		}
+	}
+
+	pub fn press(&mut self, keys: &str) -> Result<(), Error> {
+		if self.use_xdotool {
+			self.press_xdotool(keys)
+		} else {
+			self.press_libxdo(keys)
+		}
+	}
+
+	fn press_xdotool(&mut self, keys: &str) -> Result<(), Error> {
+		Command::new("sh")
+			.arg("-c")
+			.arg(self.script.clone() + keys)
+			.output()?;
+		Ok(())
+	}
+
+	fn press_libxdo(&mut self, keys: &str) -> Result<(), Error> {
+		/* xdotool(1): Delay between keystrokes. Default is 12ms. */
+		const DELAY_US: u32 = 12_000;
+
+		let window = if self.no_search {
+			None
+		} else {
+			if self.window.is_none() {
+				self.window = self
+					.xdo
+					.search_windows(Search {
+						only_visible: true,
+						window_class: Some(Self::BROWSER_WINDOW_CLASS_REGEX.to_string()),
+						limit: 1,
+						..Search::default()
+					})
+					.inspect_err(|err| error!("Unable to find browser window: {err}"))
+					.ok()
+					.and_then(|windows| {
+						if windows.is_empty() {
+							error!("No browser windows found");
+						} else if windows.len() > 2 {
+							warn!("Multiple browser windows found: {windows:?}");
+						} else {
+							trace!("Found one browser window: {}", windows[0]);
+						}
+						windows.first().copied()
+					});
+			}
+
+			Some(self.window.ok_or(anyhow!("Browser window not found"))?)
+		};
+
+		self.xdo
+			.send_keysequence(window, keys, DELAY_US)
+			.inspect_err(|_| self.window = None)?;
+		Ok(())
+	}
+}
+
+impl Eyes {
+	fn new(session: Session) -> Self {
+		match session {
+			Session::X11 => Self::Xcap(Monitor::all().unwrap()),
+			Session::Wayland => Self::Grim(Grim),
+		}
+	}
+
+	pub fn see(&self) -> Option<String> {
+		let monitors = match self {
+			Self::Xcap(monitors) => monitors,
+			Self::Grim(grim) => return grim.see(),
+		};
+
+		monitors
+			.first()
+			.and_then(|monitor| match monitor.capture_image() {
+				Ok(image) => {
+					let mut hasher = Sha256::new();
+					hasher.update(image.as_raw());
+					let hash = hasher.finalize();
+					Some(format!("{:x}", hash))
+				}
+
+				Err(err) => {
+					error!("Unable to capture image: {err}");
+					None
+				}
+			})
+	}
+}
+
+#[cfg(test)]
+pub mod fake {
+	use std::sync::{Arc, Mutex};
+
+	use anyhow::{Error, anyhow};
+
+	use super::Backend;
+
+	/// In-memory browser that records what it was asked to do
+	#[derive(Debug, Default, Clone)]
+	pub struct Fake {
+		pub actions: Arc<Mutex<Vec<String>>>,
+		pub screen: Arc<Mutex<Option<String>>>,
+		pub url: Arc<Mutex<Option<String>>>,
+		/// Keys that fail to be pressed
+		pub fail: Arc<Mutex<Vec<String>>>,
+	}
+
+	impl Fake {
+		/// Take the recorded actions
+		pub fn take(&self) -> Vec<String> {
+			std::mem::take(&mut self.actions.lock().unwrap())
+		}
+
+		fn record(&self, action: String) -> Result<(), Error> {
+			self.actions.lock().unwrap().push(action);
+			Ok(())
+		}
+	}
+
+	impl Backend for Fake {
+		fn launch(&self, urls: &[String]) -> Result<(), Error> {
+			self.record(format!("launch {}", urls.join(" ")))
+		}
+
+		fn wait(&self) -> Result<(), Error> {
+			Ok(())
+		}
+
+		fn press(&self, tab: usize, keys: &str) -> Result<(), Error> {
+			if self.fail.lock().unwrap().iter().any(|fail| fail == keys) {
+				return Err(anyhow!("unable to press {keys}"));
+			}
+			self.record(format!("press {tab} {keys}"))
+		}
+
+		fn capture(&self) -> Option<String> {
+			self.screen.lock().unwrap().clone()
+		}
+
+		fn active_url(&self, _tab: usize) -> Option<String> {
+			self.url.lock().unwrap().clone()
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::output::Browser;
+
+	use super::tab_keys;
+
+	/// Apply key presses the way the browser would
+	fn simulate(mut tab: usize, count: usize, keys: &[String]) -> usize {
+		let last = count + Browser::FIRST_TAB - 1;
+
+		for key in keys {
+			tab = match key.as_str() {
+				"Ctrl+Tab" if tab == last => Browser::FIRST_TAB,
+				"Ctrl+Tab" => tab + 1,
+				"Ctrl+Shift+Tab" if tab == Browser::FIRST_TAB => last,
+				"Ctrl+Shift+Tab" => tab - 1,
+				"Ctrl+9" => last,
+				_ => {
+					let direct: usize = key.strip_prefix("Ctrl+").unwrap().parse().unwrap();
+
+					/* Missing tabs are ignored */
+					if direct <= last { direct } else { tab }
+				}
+			};
+		}
+
+		tab
+	}
+
+	#[test]
+	fn all_tabs_reachable() {
+		for count in 1..=30 {
+			let last = count + Browser::FIRST_TAB - 1;
+			/* Worst case is halfway between the last direct tab and the last tab */
+			let max_keys = 1 + count.saturating_sub(8) / 2;
+
+			for current in Browser::FIRST_TAB..=last {
+				for tab in Browser::FIRST_TAB..=last {
+					let keys = tab_keys(current, tab, count).unwrap();
+
+					assert_eq!(
+						simulate(current, count, &keys),
+						tab,
+						"{current} to {tab} of {count}: {keys:?}"
+					);
+					assert!(
+						keys.len() <= max_keys,
+						"{current} to {tab} of {count}: {keys:?}"
+					);
+					assert_eq!(keys.is_empty(), current == tab);
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn shortest_sequence() {
+		let plan = |current, tab, count| tab_keys(current, tab, count).unwrap().join(" ");
+
+		assert_eq!(plan(1, 3, 3), "Ctrl+3");
+		assert_eq!(plan(12, 8, 20), "Ctrl+8");
+		assert_eq!(plan(1, 20, 20), "Ctrl+9");
+		assert_eq!(plan(1, 10, 20), "Ctrl+8 Ctrl+Tab Ctrl+Tab");
+		assert_eq!(plan(1, 18, 20), "Ctrl+9 Ctrl+Shift+Tab Ctrl+Shift+Tab");
+		assert_eq!(plan(12, 14, 20), "Ctrl+Tab Ctrl+Tab");
+		assert_eq!(plan(14, 12, 20), "Ctrl+Shift+Tab Ctrl+Shift+Tab");
+		assert_eq!(plan(20, 1, 20), "Ctrl+1");
+		assert_eq!(plan(10, 11, 20), "Ctrl+Tab");
+		assert_eq!(plan(1, 1, 1), "");
+	}
+
+	#[test]
+	fn invalid_tabs() {
+		assert!(tab_keys(1, 0, 5).is_err());
+		assert!(tab_keys(1, 6, 5).is_err());
+		assert!(tab_keys(0, 1, 5).is_err());
+		assert!(tab_keys(6, 1, 5).is_err());
+		assert!(tab_keys(1, 1, 0).is_err());
+	}
+}
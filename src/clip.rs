@@ -0,0 +1,281 @@
+/*
+ * Copyright 2025  Simon Arlott
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use std::{
+	collections::VecDeque,
+	path::{Component, Path, PathBuf},
+	process::{Child, Command, Stdio},
+	sync::{Arc, Condvar, Mutex, MutexGuard},
+	thread,
+	time::Duration,
+};
+
+use anyhow::{Context, Error, anyhow};
+use log::{debug, error, info, warn};
+use rumqttc::QoS;
+
+use crate::config::{ClipsSection, Config};
+use crate::mqtt::Mqtt;
+use crate::output::Browser;
+
+/// Plays video clips requested over MQTT, one at a time
+#[derive(Debug)]
+pub struct ClipPlayer {
+	config: Arc<Config>,
+	browser: Arc<Browser>,
+	mqtt: Arc<Mqtt>,
+	state: Mutex<PlayerState>,
+	changed: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct PlayerState {
+	queue: VecDeque<(String, PathBuf)>,
+	playing: Option<Playing>,
+}
+
+#[derive(Debug)]
+struct Playing {
+	name: String,
+	child: Child,
+}
+
+impl ClipPlayer {
+	/// Time between checks for the player exiting
+	const POLL: Duration = Duration::from_millis(250);
+
+	pub fn new(config: Arc<Config>, browser: Arc<Browser>, mqtt: Arc<Mqtt>) -> Arc<Self> {
+		Arc::new(Self {
+			config,
+			browser,
+			mqtt,
+			state: Mutex::new(PlayerState::default()),
+			changed: Condvar::new(),
+		})
+	}
+
+	pub fn start(self: &Arc<Self>) {
+		let self_copy = self.clone();
+		let prefix = format!("{}/", self.mqtt.topics().clip);
+		let messages = self.mqtt.subscribe(&format!("{prefix}#"), QoS::AtLeastOnce);
+
+		thread::spawn(move || {
+			for msg in messages {
+				match msg.topic.strip_prefix(&prefix) {
+					Some("play") => self_copy.play(&String::from_utf8_lossy(&msg.payload)),
+					Some("skip") => self_copy.skip(),
+					Some("stop") => self_copy.stop(),
+					_ => warn!("[CLIP] Unknown clip topic: {}", msg.topic),
+				}
+			}
+		});
+
+		let self_copy = self.clone();
+
+		thread::spawn(move || self_copy.run());
+	}
+
+	fn play(&self, name: &str) {
+		let clips = self.config.clips();
+		let Some(directory) = clips.directory() else {
+			error!("[CLIP] No clips directory");
+			return;
+		};
+		let path = match resolve(&directory, name) {
+			Ok(path) => path,
+			Err(err) => {
+				warn!("[CLIP] Unable to play {name:?}: {err}");
+				return;
+			}
+		};
+		let mut state = self.state.lock().unwrap();
+
+		if state.queue.len() >= clips.queue {
+			warn!("[CLIP] Queue full, not playing {name:?}");
+			return;
+		}
+
+		info!("[CLIP] Queued {name:?}");
+		state.queue.push_back((name.to_owned(), path));
+		self.changed.notify_all();
+	}
+
+	/// Stop the current clip and play the next one
+	fn skip(&self) {
+		Self::kill(&mut self.state.lock().unwrap());
+	}
+
+	/// Stop the current clip and remove all of the queued clips
+	fn stop(&self) {
+		let mut state = self.state.lock().unwrap();
+
+		state.queue.clear();
+		Self::kill(&mut state);
+	}
+
+	fn kill(state: &mut MutexGuard<PlayerState>) {
+		if let Some(playing) = &mut state.playing {
+			info!("[CLIP] Stopping {:?}", playing.name);
+			if let Err(err) = playing.child.kill() {
+				error!("[CLIP] Unable to stop player: {err}");
+			}
+		}
+	}
+
+	fn run(&self) {
+		let mut state = self.state.lock().unwrap();
+
+		self.publish("");
+
+		loop {
+			if let Some(playing) = &mut state.playing {
+				match playing.child.try_wait() {
+					Ok(None) => {
+						state = self.changed.wait_timeout(state, Self::POLL).unwrap().0;
+						continue;
+					}
+					Ok(Some(status)) => debug!("[CLIP] Finished {:?}: {status}", playing.name),
+					Err(err) => error!("[CLIP] Unable to wait for player: {err}"),
+				}
+
+				state.playing = None;
+				if state.queue.is_empty() {
+					self.publish("");
+				}
+			}
+
+			match state.queue.pop_front() {
+				Some((name, path)) => match command(&self.config.clips(), &path).spawn() {
+					Ok(child) => {
+						info!("[CLIP] Playing {name:?}");
+						self.browser.user_activity();
+						self.publish(&name);
+						state.playing = Some(Playing { name, child });
+					}
+					Err(err) => error!("[CLIP] Unable to play {name:?}: {err}"),
+				},
+				None => state = self.changed.wait(state).unwrap(),
+			}
+		}
+	}
+
+	/// Publish the name of the clip that is playing
+	fn publish(&self, name: &str) {
+		self.mqtt.publish(
+			self.mqtt.topics().state("clip"),
+			QoS::AtLeastOnce,
+			true,
+			name,
+		);
+	}
+}
+
+/// Find a clip in the directory, rejecting names that refer to anything
+/// outside of it
+fn resolve(directory: &Path, name: &str) -> Result<PathBuf, Error> {
+	if name.is_empty()
+		|| !Path::new(name)
+			.components()
+			.all(|component| matches!(component, Component::Normal(_)))
+	{
+		return Err(anyhow!("invalid name"));
+	}
+
+	let directory = directory
+		.canonicalize()
+		.with_context(|| format!("unable to access {:?}", directory.display()))?;
+	let path = directory.join(name).canonicalize().context("not found")?;
+
+	if !path.starts_with(&directory) {
+		Err(anyhow!("outside of clips directory"))
+	} else if !path.is_file() {
+		Err(anyhow!("not a file"))
+	} else {
+		Ok(path)
+	}
+}
+
+/// Player command for a clip (without a shell)
+fn command(clips: &ClipsSection, path: &Path) -> Command {
+	/* Validated when the config is loaded */
+	let (program, args) = clips.command.split_first().unwrap();
+	let mut command = Command::new(program);
+
+	command.args(args).arg(path).stdin(Stdio::null());
+	if !clips.display.is_empty() {
+		command.env("DISPLAY", &clips.display);
+	}
+	command
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{env, fs, os::unix, path::Path, process};
+
+	use crate::config::ClipsSection;
+
+	use super::{command, resolve};
+
+	#[test]
+	fn resolve_clips() {
+		let directory = env::temp_dir().join(format!("status-screen-clips-{}", process::id()));
+		let clips = directory.join("clips");
+
+		fs::create_dir_all(clips.join("sub")).unwrap();
+		fs::write(clips.join("a.mp4"), "").unwrap();
+		fs::write(clips.join("sub/b.mkv"), "").unwrap();
+		fs::write(directory.join("secret"), "").unwrap();
+		unix::fs::symlink(directory.join("secret"), clips.join("escape")).unwrap();
+
+		let resolve = |name| resolve(&clips, name).map_err(|err| err.to_string());
+		let canonical = clips.canonicalize().unwrap();
+
+		assert_eq!(resolve("a.mp4"), Ok(canonical.join("a.mp4")));
+		assert_eq!(resolve("sub/b.mkv"), Ok(canonical.join("sub/b.mkv")));
+		assert_eq!(resolve(""), Err("invalid name".to_owned()));
+		assert_eq!(resolve("../secret"), Err("invalid name".to_owned()));
+		assert_eq!(resolve("sub/../a.mp4"), Err("invalid name".to_owned()));
+		assert_eq!(resolve("/etc/passwd"), Err("invalid name".to_owned()));
+		assert_eq!(resolve("$(reboot)"), Err("not found".to_owned()));
+		assert_eq!(resolve("sub"), Err("not a file".to_owned()));
+		assert_eq!(
+			resolve("escape"),
+			Err("outside of clips directory".to_owned())
+		);
+
+		fs::remove_dir_all(directory).unwrap();
+	}
+
+	#[test]
+	fn player_command() {
+		let clips = ClipsSection {
+			command: vec!["mpv".to_owned(), "--fs".to_owned()],
+			..ClipsSection::default()
+		};
+		let command = command(&clips, Path::new("/clips/a b.mp4"));
+
+		assert_eq!(command.get_program(), "mpv");
+		assert_eq!(
+			command.get_args().collect::<Vec<_>>(),
+			["--fs", "/clips/a b.mp4"]
+		);
+		assert_eq!(
+			command.get_envs().collect::<Vec<_>>(),
+			[("DISPLAY".as_ref(), Some(":0".as_ref()))]
+		);
+	}
+}
@@ -16,16 +16,27 @@
  */
 
 use std::{
-	path::PathBuf,
-	sync::{Arc, Mutex},
+	env, fmt, fs,
+	net::SocketAddr,
+	path::{Path, PathBuf},
+	sync::{
+		Arc, Mutex,
+		mpsc::{self, Receiver, Sender},
+	},
 	thread,
 	time::Duration,
 };
 
 use anyhow::{Error, anyhow};
-use config::Value;
+use chrono::{Datelike, NaiveDateTime, NaiveTime, Weekday};
 use indexmap::IndexMap;
-use log::{error, trace, warn};
+use log::{debug, error, info, trace};
+use notify::{RecursiveMode, Watcher};
+use serde::Deserialize;
+
+use crate::display::DisplayPower;
+use crate::mapping::{Action, Axis, Mapping};
+use crate::sequence::Sequence;
 
 #[derive(Debug, Default, clap::Parser)]
 #[command()]
@@ -41,10 +52,22 @@ pub struct CommandLineArgs {
 	#[arg(short, long = "xdotool")]
 	pub xdotool: bool,
 
+	/// Control the browser using the DevTools protocol instead of key presses
+	#[arg(short, long, value_names = ["PORT"], num_args = 0..=1, default_missing_value = "9222")]
+	pub devtools: Option<u16>,
+
 	/// Send keys to the current window without searching
 	#[arg(short, long)]
 	pub no_search: bool,
 
+	/// Check the config file and exit
+	#[arg(long)]
+	pub check_config: bool,
+
+	/// Print the codes of buttons and axes as they're used
+	#[arg(long)]
+	pub learn: bool,
+
 	/// Debug logging
 	#[arg(short, long, action = clap::ArgAction::Count)]
 	pub verbose: u8,
@@ -54,246 +77,1668 @@ pub struct CommandLineArgs {
 pub struct Config {
 	config_file: String,
 	state: Mutex<State>,
+	subscribers: Mutex<Vec<Sender<Vec<&'static str>>>>,
 }
 
-#[derive(Debug)]
+/// Contents of the config file
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 struct State {
-	data: IndexMap<String, Value>,
-	autoscroll_delay: Duration,
-	autoscroll_hold: Duration,
-	autoscroll_pause: Duration,
+	keyboards: IndexMap<String, KeyboardSection>,
+	devices: IndexMap<String, DeviceSection>,
+	urls: IndexMap<String, String>,
+	mqtt: Option<MqttSection>,
+	homeassistant: Option<HomeAssistantSection>,
+	http: Option<HttpSection>,
+	autoscroll: AutoscrollSection,
+	reload: IndexMap<String, u64>,
+	dwell: IndexMap<String, u64>,
+	weight: IndexMap<String, u64>,
+	tabs: IndexMap<u16, String>,
+	timers: IndexMap<u16, String>,
+	actions: IndexMap<String, String>,
+	display: DisplayPower,
+	sleep: SleepSection,
+	sequences: IndexMap<String, SequenceSection>,
+	clips: ClipsSection,
+	schedule: IndexMap<String, ScheduleSection>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged, expecting = "a device path or device details")]
+enum KeyboardSection {
+	Path(PathBuf),
+	Match(DeviceMatch),
+}
+
+/// Bindings of the inputs of a keyboard to actions
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct DeviceSection {
+	keys: IndexMap<u16, String>,
+	axes: IndexMap<u16, AxisSection>,
+	hats: IndexMap<u16, HatSection>,
+	long: IndexMap<u16, String>,
+	double: IndexMap<u16, String>,
+	/// Pairs of key codes ("288+289")
+	chords: IndexMap<String, String>,
+	/// Milliseconds
+	long_press: u64,
+	/// Milliseconds
+	double_press: u64,
+	/// Exclusive access to the device (when it's opened)
+	grab: bool,
+	/// Keys to send to the rest of the system through a virtual keyboard
+	passthrough: Vec<u16>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct AxisSection {
+	centre: i32,
+	threshold: i32,
+	low: Option<String>,
+	high: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct HatSection {
+	up: Option<String>,
+	down: Option<String>,
+	left: Option<String>,
+	right: Option<String>,
+}
+
+/// Which input device to use for a keyboard (all of the specified values
+/// must match)
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DeviceMatch {
+	/// Device node or a symlink to it
+	pub path: Option<PathBuf>,
+	pub vendor: Option<u16>,
+	pub product: Option<u16>,
+	pub serial: Option<String>,
+	/// Device name (glob pattern)
+	pub name: Option<String>,
 }
 
+/// MQTT broker connection
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MqttSection {
+	pub hostname: String,
+	pub port: u16,
+	pub client_id: String,
+	pub username: Option<String>,
+	pub password: Option<String>,
+	/// CA certificate file (connect using TLS)
+	pub tls_ca: Option<PathBuf>,
+	/// Keep alive interval in seconds (0 to disable)
+	pub keep_alive: u64,
+	pub topics: MqttTopics,
+	pub presence: PresencePayload,
+}
+
+/// MQTT topic names
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MqttTopics {
+	/// Prefix of the state and command topics of the status screen
+	pub prefix: String,
+	pub presence: String,
+	/// Prefix of the clip topics
+	pub clip: String,
+	pub timer_reset: String,
+}
+
+/// Payload values of presence messages
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PresencePayload {
+	/// Location of the value in a JSON payload
+	pub json_pointer: Option<String>,
+	pub empty: Vec<String>,
+	/// Values when present (anything that isn't empty, if not specified)
+	pub present: Vec<String>,
+}
+
+/// Playback of video clips
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClipsSection {
+	/// Directory containing the clips (~/clips, if not specified)
+	pub directory: Option<PathBuf>,
+	/// Player command and arguments (the clip is appended)
+	pub command: Vec<String>,
+	/// X display of the player (inherited, if empty)
+	pub display: String,
+	/// Maximum number of clips waiting to be played
+	pub queue: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct HomeAssistantSection {
+	discovery_prefix: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HttpSection {
+	listen: String,
+}
+
+/// Sequence of inputs on a keyboard that performs an action
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct SequenceSection {
+	keyboard: String,
+	inputs: Vec<String>,
+	action: String,
+	/// Maximum time between inputs (seconds)
+	timeout: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct AutoscrollSection {
+	delay: u64,
+	hold: u64,
+	pause: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct SleepSection {
+	delay: u64,
+	awake: u64,
+	idle: Option<u64>,
+	never: Option<ScheduleSection>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ScheduleSection {
+	days: Vec<String>,
+	times: Vec<String>,
+	space_open: Option<bool>,
+}
+
+/// Problem found when checking the config file
 #[derive(Debug)]
+struct Problem {
+	section: String,
+	key: Option<String>,
+	message: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Page {
 	pub url: String,
 	pub reload: Option<Duration>,
+	pub dwell: Option<Duration>,
+	pub weight: u64,
+	pub schedule: Schedule,
+}
+
+/// When a page is in the autoscroll rotation (always, if empty)
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Schedule {
+	days: Vec<Weekday>,
+	/// Start and end times (the end time may be on the next day)
+	times: Vec<(NaiveTime, NaiveTime)>,
+	space_open: Option<bool>,
 }
 
 impl Config {
-	pub fn new(args: &CommandLineArgs) -> Arc<Self> {
-		let config_file = args.config_file.to_str().unwrap();
+	pub fn new(args: &CommandLineArgs) -> Result<Arc<Self>, Error> {
+		let config_file = args
+			.config_file
+			.to_str()
+			.ok_or(anyhow!("Invalid config filename"))?;
 
-		Arc::new(Self {
+		Ok(Arc::new(Self {
 			config_file: config_file.to_owned(),
-			state: Mutex::new(State::new(
-				config::Config::builder()
-					.add_source(config::File::with_name(config_file))
-					.build()
-					.unwrap()
-					.try_deserialize::<IndexMap<String, Value>>()
-					.unwrap(),
-			)),
+			state: Mutex::new(State::load(config_file)?),
+			subscribers: Mutex::new(Vec::new()),
+		}))
+	}
+
+	/// Load config from a string instead of a file
+	#[cfg(test)]
+	pub fn from_toml(source: &str) -> Arc<Self> {
+		Arc::new(Self {
+			config_file: String::new(),
+			state: Mutex::new(State::from_toml(source)),
+			subscribers: Mutex::new(Vec::new()),
 		})
 	}
 
+	/// Replace the config with a string (without notifying subscribers)
+	#[cfg(test)]
+	pub fn replace_toml(&self, source: &str) {
+		*self.state.lock().unwrap() = State::from_toml(source);
+	}
+
+	/// Check the config file, print a report and return whether it is valid
+	pub fn check(args: &CommandLineArgs) -> bool {
+		let config_file = args.config_file.as_path();
+		let path = Self::path(config_file);
+		let source = fs::read_to_string(&path).unwrap_or_default();
+		let filename = path.display();
+
+		let problems = match config_file
+			.to_str()
+			.ok_or(anyhow!("Invalid config filename"))
+			.and_then(|config_file| {
+				Ok(config::Config::builder()
+					.add_source(config::File::with_name(config_file))
+					.build()?
+					.try_deserialize::<State>()?)
+			}) {
+			Ok(state) => state.check(),
+			Err(err) => {
+				println!("{filename}: {err:#}");
+				return false;
+			}
+		};
+
+		for problem in problems.iter() {
+			match problem.line(&source) {
+				Some(line) => println!("{filename}:{line}: {problem}"),
+				None => println!("{filename}: {problem}"),
+			}
+		}
+
+		if problems.is_empty() {
+			println!("{filename}: OK");
+			true
+		} else {
+			println!("{filename}: {} problem(s) found", problems.len());
+			false
+		}
+	}
+
+	/// Find the actual file that will be loaded for the config file name
+	fn path(config_file: &Path) -> PathBuf {
+		[
+			config_file.to_path_buf(),
+			config_file.with_extension("toml"),
+		]
+		.into_iter()
+		.find(|path| path.is_file())
+		.unwrap_or(config_file.to_path_buf())
+	}
+
 	pub fn start(self: &Arc<Self>) {
 		let self_copy = self.clone();
 
 		thread::spawn(move || {
+			if let Err(err) = self_copy.watch() {
+				error!("Unable to watch config file (polling instead): {err}");
+			}
+
 			loop {
 				thread::sleep(Duration::from_secs(60));
+				self_copy.reload();
+			}
+		});
+	}
 
-				let mut state = self_copy.state.lock().unwrap();
+	fn watch(&self) -> Result<(), Error> {
+		/* Wait for editors to finish writing */
+		const DEBOUNCE: Duration = Duration::from_millis(500);
 
-				trace!("Reloading config");
+		let path = Self::path(Path::new(&self.config_file));
+		let filename = path.file_name().ok_or(anyhow!("Invalid config filename"))?;
+		let (tx, rx) = mpsc::channel();
+		let mut watcher = notify::recommended_watcher(tx)?;
 
-				if let Ok(new_data) = config::Config::builder()
-					.add_source(config::File::with_name(self_copy.config_file.as_str()))
-					.build()
-					.inspect_err(|err| error!("Config file error: {err}"))
-					.and_then(|config| config.try_deserialize::<IndexMap<String, Value>>())
-				{
-					*state = State::new(new_data);
+		/* Watch the directory to handle editors that replace the file */
+		watcher.watch(
+			path.parent()
+				.filter(|dir| !dir.as_os_str().is_empty())
+				.unwrap_or(Path::new(".")),
+			RecursiveMode::NonRecursive,
+		)?;
+		debug!("Watching config file {:?}", path.display());
+
+		loop {
+			let event: notify::Event = rx.recv()??;
+
+			if event.kind.is_access()
+				|| !event
+					.paths
+					.iter()
+					.any(|path| path.file_name() == Some(filename))
+			{
+				continue;
+			}
+
+			trace!("Config file event: {event:?}");
+			while rx.recv_timeout(DEBOUNCE).is_ok() {}
+			self.reload();
+		}
+	}
+
+	fn reload(&self) {
+		let mut state = self.state.lock().unwrap();
+
+		trace!("Reloading config");
+
+		match State::load(&self.config_file) {
+			Ok(new_state) => {
+				let changed = state.changed_sections(&new_state);
+
+				*state = new_state;
+				drop(state);
 
-					trace!("Reloaded config");
+				if changed.is_empty() {
+					debug!("Reloaded config (unchanged)");
+				} else {
+					info!("Reloaded config (changed: {})", changed.join(", "));
+
+					self.subscribers
+						.lock()
+						.unwrap()
+						.retain(|subscriber| subscriber.send(changed.clone()).is_ok());
 				}
 			}
-		});
+			Err(err) => error!("Config file error: {err:#}"),
+		}
 	}
 
-	pub fn browser_urls(&self) -> Result<IndexMap<String, Page>, Error> {
+	/// Receive the names of the sections that changed on every reload
+	pub fn subscribe(&self) -> Receiver<Vec<&'static str>> {
+		let (tx, rx) = mpsc::channel();
+
+		self.subscribers.lock().unwrap().push(tx);
+		rx
+	}
+
+	pub fn browser_urls(&self) -> IndexMap<String, Page> {
 		let state = self.state.lock().unwrap();
-		let reload_section = state.data.get("reload").and_then(|section| {
-			section
-				.clone()
-				.into_table()
-				.inspect_err(|err| warn!("Invalid reload section in config: {err}"))
-				.ok()
-		});
 
-		Ok(state
-			.data
-			.get("urls")
-			.ok_or(anyhow!("No urls in config"))?
-			.clone()
-			.into_table()?
-			.clone()
+		state
+			.urls
 			.iter()
-			.filter_map(|(name, url)| match url.clone().into_string() {
-				Ok(url) => {
-					let reload = reload_section
-						.as_ref()
-						.and_then(|table| {
-							table.clone().get(name).and_then(|value| {
-								value
-									.clone()
-									.into_uint()
-									.inspect_err(|err| {
-										warn!("Invalid reload value for {name}: {err}")
-									})
-									.ok()
-							})
-						})
-						.map(Duration::from_secs);
-					Some((name.clone(), Page { url, reload }))
-				}
-
-				Err(err) => {
-					error!("Invalid url string for {name}: {err}");
-					None
-				}
+			.map(|(name, url)| {
+				(
+					name.clone(),
+					Page {
+						url: url.clone(),
+						reload: state.reload.get(name).copied().map(Duration::from_secs),
+						dwell: state.dwell.get(name).copied().map(Duration::from_secs),
+						weight: state.weight.get(name).copied().unwrap_or(1),
+						/* Validated when the config is loaded */
+						schedule: state
+							.schedule
+							.get(name)
+							.map(|schedule| schedule.parse().unwrap())
+							.unwrap_or_default(),
+					},
+				)
 			})
-			.collect())
+			.collect()
 	}
 
 	pub fn autoscroll_delay(&self) -> Duration {
 		let state = self.state.lock().unwrap();
 
-		state.autoscroll_delay
+		Duration::from_secs(state.autoscroll.delay)
 	}
 
 	pub fn autoscroll_hold(&self) -> Duration {
 		let state = self.state.lock().unwrap();
 
-		state.autoscroll_hold
+		Duration::from_secs(state.autoscroll.hold)
 	}
 
 	pub fn autoscroll_pause(&self) -> Duration {
 		let state = self.state.lock().unwrap();
 
-		state.autoscroll_pause
+		Duration::from_secs(state.autoscroll.pause)
 	}
 
-	pub fn keyboard_device(&self, name: &str) -> Result<String, Error> {
+	pub fn sleep_delay(&self) -> Duration {
 		let state = self.state.lock().unwrap();
 
-		Ok(state
-			.data
-			.get("keyboards")
-			.ok_or(anyhow!("No keyboards in config"))?
-			.clone()
-			.into_table()?
-			.get(name)
-			.ok_or(anyhow!("Missing keyboard: {name}"))?
-			.clone()
-			.into_string()?
-			.clone())
+		Duration::from_secs(state.sleep.delay)
 	}
 
-	pub fn tabs_key(&self, id: u16) -> Result<String, Error> {
+	pub fn sleep_awake(&self) -> Duration {
 		let state = self.state.lock().unwrap();
 
-		Ok(state
-			.data
-			.get("tabs")
-			.ok_or(anyhow!("No tab keys in config"))?
-			.clone()
-			.into_table()?
-			.get(&id.to_string())
-			.ok_or(anyhow!("Missing key: {id}"))?
-			.clone()
-			.into_string()?
-			.clone())
+		Duration::from_secs(state.sleep.awake)
 	}
 
-	pub fn timers_key(&self, id: u16) -> Result<String, Error> {
+	/// Time without input before sleeping (when presence is not known)
+	pub fn sleep_idle(&self) -> Option<Duration> {
 		let state = self.state.lock().unwrap();
 
-		Ok(state
-			.data
-			.get("timers")
-			.ok_or(anyhow!("No timer keys in config"))?
-			.clone()
-			.into_table()?
-			.get(&id.to_string())
-			.ok_or(anyhow!("Missing key: {id}"))?
-			.clone()
-			.into_string()?
-			.clone())
+		state.sleep.idle.map(Duration::from_secs)
 	}
 
-	pub fn konami_command(&self) -> Result<String, Error> {
+	/// When the display should never sleep automatically
+	pub fn sleep_never(&self) -> Option<Schedule> {
 		let state = self.state.lock().unwrap();
 
-		Ok(state
-			.data
-			.get("main")
-			.ok_or(anyhow!("No main section in config"))?
-			.clone()
-			.into_table()?
-			.get("konami")
-			.ok_or(anyhow!("No konami setting in config"))?
-			.clone()
-			.into_string()?
-			.clone())
+		/* Validated when the config is loaded */
+		state
+			.sleep
+			.never
+			.as_ref()
+			.map(|schedule| schedule.parse().unwrap())
 	}
 
-	pub fn mqtt_hostname(&self) -> Result<String, Error> {
+	pub fn keyboard_names(&self) -> Vec<String> {
 		let state = self.state.lock().unwrap();
 
-		Ok(state
-			.data
-			.get("mqtt")
-			.ok_or(anyhow!("No mqtt section in config"))?
-			.clone()
-			.into_table()?
-			.get("hostname")
-			.ok_or(anyhow!("No hostname setting in config"))?
-			.clone()
-			.into_string()?
-			.clone())
+		state.keyboards.keys().cloned().collect()
+	}
+
+	pub fn keyboard(&self, name: &str) -> Option<DeviceMatch> {
+		let state = self.state.lock().unwrap();
+
+		state.keyboards.get(name).map(|keyboard| match keyboard {
+			KeyboardSection::Path(path) => DeviceMatch {
+				path: Some(path.clone()),
+				..DeviceMatch::default()
+			},
+			KeyboardSection::Match(device) => device.clone(),
+		})
+	}
+
+	/// Bindings of the inputs of a keyboard
+	pub fn device_mapping(&self, name: &str) -> Mapping {
+		let state = self.state.lock().unwrap();
+
+		match state.devices.get(name) {
+			/* Validated when the config is loaded */
+			Some(device) => device.parse(name).unwrap(),
+			None => Mapping::default(),
+		}
+	}
+
+	pub fn device_grab(&self, name: &str) -> bool {
+		let state = self.state.lock().unwrap();
+
+		state.devices.get(name).is_some_and(|device| device.grab)
+	}
+
+	pub fn tabs_key(&self, id: u16) -> Option<String> {
+		let state = self.state.lock().unwrap();
+
+		state.tabs.get(&id).cloned()
+	}
+
+	pub fn timers_key(&self, id: u16) -> Option<String> {
+		let state = self.state.lock().unwrap();
+
+		state.timers.get(&id).cloned()
+	}
+
+	pub fn timer_configured(&self, name: &str) -> bool {
+		let state = self.state.lock().unwrap();
+
+		state.timers.values().any(|timer| timer == name)
+	}
+
+	pub fn timers(&self) -> IndexMap<u16, String> {
+		let state = self.state.lock().unwrap();
+
+		state.timers.clone()
+	}
+
+	pub fn action_command(&self, name: &str) -> Option<String> {
+		let state = self.state.lock().unwrap();
+
+		state.actions.get(name).cloned()
+	}
+
+	/// Sequences that can be entered on a keyboard
+	pub fn sequences(&self, keyboard: &str) -> Vec<Sequence> {
+		let state = self.state.lock().unwrap();
+
+		state
+			.sequences
+			.iter()
+			.filter(|(_, sequence)| sequence.keyboard == keyboard)
+			/* Validated when the config is loaded */
+			.map(|(name, sequence)| sequence.parse(name).unwrap())
+			.collect()
+	}
+
+	pub fn http_listen(&self) -> Option<String> {
+		let state = self.state.lock().unwrap();
+
+		state.http.as_ref().map(|http| http.listen.clone())
+	}
+
+	pub fn display_power(&self) -> DisplayPower {
+		let state = self.state.lock().unwrap();
+
+		state.display.clone()
+	}
+
+	pub fn clips(&self) -> ClipsSection {
+		let state = self.state.lock().unwrap();
+
+		state.clips.clone()
+	}
+
+	pub fn mqtt(&self) -> Option<MqttSection> {
+		let state = self.state.lock().unwrap();
+
+		state.mqtt.clone()
+	}
+
+	pub fn mqtt_topics(&self) -> MqttTopics {
+		let state = self.state.lock().unwrap();
+
+		state
+			.mqtt
+			.as_ref()
+			.map(|mqtt| mqtt.topics.clone())
+			.unwrap_or_default()
+	}
+
+	pub fn presence_payload(&self) -> PresencePayload {
+		let state = self.state.lock().unwrap();
+
+		state
+			.mqtt
+			.as_ref()
+			.map(|mqtt| mqtt.presence.clone())
+			.unwrap_or_default()
+	}
+
+	pub fn homeassistant_discovery_prefix(&self) -> Option<String> {
+		let state = self.state.lock().unwrap();
+
+		state
+			.homeassistant
+			.as_ref()
+			.map(|homeassistant| homeassistant.discovery_prefix.clone())
 	}
 }
 
 impl State {
-	pub fn new(data: IndexMap<String, Value>) -> Self {
-		let autoscroll_section = data.get("autoscroll").and_then(|section| {
-			section
-				.clone()
-				.into_table()
-				.inspect_err(|err| warn!("Invalid autoscroll section in config: {err}"))
-				.ok()
-		});
+	const EVDEV_PREFIX: &str = "/dev/input/";
 
-		Self {
-			data,
-			autoscroll_delay: Self::autoscroll_config(&autoscroll_section, "delay", 20),
-			autoscroll_hold: Self::autoscroll_config(&autoscroll_section, "hold", 60),
-			autoscroll_pause: Self::autoscroll_config(&autoscroll_section, "pause", 900),
-		}
-	}
-
-	fn autoscroll_config(
-		config: &Option<IndexMap<String, Value>>,
-		name: &str,
-		default_s: u64,
-	) -> Duration {
-		Duration::from_secs(
-			config
-				.as_ref()
-				.and_then(|table| {
-					table.clone().get(name).and_then(|value| {
-						value
-							.clone()
-							.into_uint()
-							.inspect_err(|err| {
-								warn!("Invalid autoscroll {name} value in config: {err}")
-							})
-							.ok()
+	fn load(config_file: &str) -> Result<Self, Error> {
+		let state = config::Config::builder()
+			.add_source(config::File::with_name(config_file))
+			.build()?
+			.try_deserialize::<Self>()?;
+
+		state.validate()?;
+		Ok(state)
+	}
+
+	#[cfg(test)]
+	fn from_toml(source: &str) -> Self {
+		let state = config::Config::builder()
+			.add_source(config::File::from_str(source, config::FileFormat::Toml))
+			.build()
+			.unwrap()
+			.try_deserialize::<Self>()
+			.unwrap();
+
+		state.validate().unwrap();
+		state
+	}
+
+	fn changed_sections(&self, other: &Self) -> Vec<&'static str> {
+		[
+			("keyboards", self.keyboards != other.keyboards),
+			("devices", self.devices != other.devices),
+			("urls", self.urls != other.urls),
+			("mqtt", self.mqtt != other.mqtt),
+			("homeassistant", self.homeassistant != other.homeassistant),
+			("http", self.http != other.http),
+			("autoscroll", self.autoscroll != other.autoscroll),
+			("reload", self.reload != other.reload),
+			("dwell", self.dwell != other.dwell),
+			("weight", self.weight != other.weight),
+			("tabs", self.tabs != other.tabs),
+			("timers", self.timers != other.timers),
+			("actions", self.actions != other.actions),
+			("display", self.display != other.display),
+			("sleep", self.sleep != other.sleep),
+			("sequences", self.sequences != other.sequences),
+			("clips", self.clips != other.clips),
+			("schedule", self.schedule != other.schedule),
+		]
+		.into_iter()
+		.filter_map(|(name, changed)| changed.then_some(name))
+		.collect()
+	}
+
+	fn validate(&self) -> Result<(), Error> {
+		match self.check().into_iter().next() {
+			Some(problem) => Err(anyhow!("{problem}")),
+			None => Ok(()),
+		}
+	}
+
+	fn check(&self) -> Vec<Problem> {
+		let mut problems = Vec::new();
+
+		for (name, keyboard) in self.keyboards.iter() {
+			let (path, device) = match keyboard {
+				KeyboardSection::Path(path) => (Some(path), None),
+				KeyboardSection::Match(device) => (device.path.as_ref(), Some(device)),
+			};
+
+			if let Some(path) = path
+				&& !path.starts_with(Self::EVDEV_PREFIX)
+			{
+				problems.push(Problem::new(
+					"keyboards",
+					name,
+					format!("not an evdev device path: {:?}", path.display()),
+				));
+			}
+
+			if let Some(device) = device {
+				if *device == DeviceMatch::default() {
+					problems.push(Problem::new("keyboards", name, "no device details"));
+				}
+
+				if let Some(pattern) = &device.name
+					&& let Err(err) = glob::Pattern::new(pattern)
+				{
+					problems.push(Problem::new(
+						"keyboards",
+						name,
+						format!("invalid name pattern {pattern:?}: {err}"),
+					));
+				}
+			}
+		}
+
+		for (name, device) in self.devices.iter() {
+			if !self.keyboards.contains_key(name) {
+				problems.push(Problem::section(
+					format!("devices.{name}"),
+					"keyboard not configured",
+				));
+			}
+
+			if !device.passthrough.is_empty() && !device.grab {
+				problems.push(Problem::new(
+					format!("devices.{name}"),
+					"passthrough",
+					"device not grabbed",
+				));
+			}
+
+			if let Err(problem) = device.parse(name) {
+				problems.push(problem);
+			}
+		}
+
+		if self.urls.is_empty() {
+			problems.push(Problem::section("urls", "no urls in config"));
+		}
+
+		for (name, url) in self.urls.iter() {
+			if !url.contains("://") {
+				problems.push(Problem::new("urls", name, format!("not a url: {url:?}")));
+			}
+		}
+
+		if let Some(mqtt) = &self.mqtt {
+			if mqtt.hostname.is_empty() {
+				problems.push(Problem::new("mqtt", "hostname", "empty hostname"));
+			}
+
+			if mqtt.port == 0 {
+				problems.push(Problem::new("mqtt", "port", "invalid value: 0"));
+			}
+
+			if mqtt.client_id.is_empty() {
+				problems.push(Problem::new("mqtt", "client_id", "empty client id"));
+			}
+
+			if mqtt.password.is_some() && mqtt.username.is_none() {
+				problems.push(Problem::new(
+					"mqtt",
+					"password",
+					"password without username",
+				));
+			}
+
+			if let Some(tls_ca) = &mqtt.tls_ca
+				&& !tls_ca.is_file()
+			{
+				problems.push(Problem::new(
+					"mqtt",
+					"tls_ca",
+					format!("file not found: {:?}", tls_ca.display()),
+				));
+			}
+
+			for (name, topic) in [
+				("prefix", &mqtt.topics.prefix),
+				("presence", &mqtt.topics.presence),
+				("clip", &mqtt.topics.clip),
+				("timer_reset", &mqtt.topics.timer_reset),
+			] {
+				if topic.is_empty() || topic.contains(['+', '#']) || topic.ends_with("/") {
+					problems.push(Problem::new(
+						"mqtt.topics",
+						name,
+						format!("invalid topic: {topic:?}"),
+					));
+				}
+			}
+
+			if let Some(pointer) = &mqtt.presence.json_pointer
+				&& !pointer.is_empty()
+				&& !pointer.starts_with("/")
+			{
+				problems.push(Problem::new(
+					"mqtt.presence",
+					"json_pointer",
+					format!("invalid JSON pointer: {pointer:?}"),
+				));
+			}
+
+			if mqtt.presence.empty.is_empty() {
+				problems.push(Problem::new("mqtt.presence", "empty", "no values"));
+			}
+
+			for value in mqtt.presence.present.iter() {
+				if mqtt.presence.empty.contains(value) {
+					problems.push(Problem::new(
+						"mqtt.presence",
+						"present",
+						format!("also an empty value: {value:?}"),
+					));
+				}
+			}
+		}
+
+		if let Some(homeassistant) = &self.homeassistant {
+			if self.mqtt.is_none() {
+				problems.push(Problem::section("homeassistant", "MQTT not configured"));
+			}
+
+			if homeassistant.discovery_prefix.is_empty() {
+				problems.push(Problem::new(
+					"homeassistant",
+					"discovery_prefix",
+					"empty prefix",
+				));
+			}
+		}
+
+		if let Some(http) = &self.http
+			&& http.listen.parse::<SocketAddr>().is_err()
+		{
+			problems.push(Problem::new(
+				"http",
+				"listen",
+				format!("invalid address: {:?}", http.listen),
+			));
+		}
+
+		if self.clips.command.first().is_none_or(String::is_empty) {
+			problems.push(Problem::new("clips", "command", "empty command"));
+		}
+
+		if self.clips.queue == 0 {
+			problems.push(Problem::new("clips", "queue", "invalid value: 0"));
+		}
+
+		for (name, sequence) in self.sequences.iter() {
+			if !self.keyboards.contains_key(&sequence.keyboard) {
+				problems.push(Problem::new(
+					format!("sequences.{name}"),
+					"keyboard",
+					format!("keyboard not configured: {:?}", sequence.keyboard),
+				));
+			}
+
+			if let Err(problem) = sequence.parse(name) {
+				problems.push(problem);
+			}
+		}
+
+		for (name, value) in [
+			("delay", self.autoscroll.delay),
+			("hold", self.autoscroll.hold),
+			("pause", self.autoscroll.pause),
+		] {
+			if value == 0 {
+				problems.push(Problem::new(
+					"autoscroll",
+					name,
+					format!("invalid value: {value}"),
+				));
+			}
+		}
+
+		if self.autoscroll.hold < self.autoscroll.delay {
+			problems.push(Problem::new(
+				"autoscroll",
+				"hold",
+				"shorter than the autoscroll delay",
+			));
+		}
+
+		if self.autoscroll.pause < self.autoscroll.hold {
+			problems.push(Problem::new(
+				"autoscroll",
+				"pause",
+				"shorter than the autoscroll hold",
+			));
+		}
+
+		for (section, values) in [
+			("reload", &self.reload),
+			("dwell", &self.dwell),
+			("weight", &self.weight),
+		] {
+			for (name, value) in values.iter() {
+				if !self.urls.contains_key(name) {
+					problems.push(Problem::new(section, name, "unknown url"));
+				} else if *value == 0 {
+					problems.push(Problem::new(
+						section,
+						name,
+						format!("invalid value: {value}"),
+					));
+				}
+			}
+		}
+
+		for (id, name) in self.tabs.iter() {
+			if let Some(command) = name.strip_prefix("!") {
+				if command.is_empty() {
+					problems.push(Problem::new("tabs", id, "empty command"));
+				}
+			} else if !self.urls.contains_key(name) {
+				problems.push(Problem::new("tabs", id, format!("unknown url: {name}")));
+			}
+		}
+
+		for (id, name) in self.timers.iter() {
+			if name.is_empty() {
+				problems.push(Problem::new("timers", id, "empty timer name"));
+			}
+		}
+
+		for (name, command) in self.actions.iter() {
+			if command.is_empty() {
+				problems.push(Problem::new("actions", name, "empty command"));
+			}
+		}
+
+		if self.sleep.idle == Some(0) {
+			problems.push(Problem::new("sleep", "idle", "invalid value: 0"));
+		}
+
+		if let Some(schedule) = &self.sleep.never
+			&& let Err(problem) = schedule.parse()
+		{
+			problems.push(Problem {
+				section: "sleep.never".to_owned(),
+				..problem
+			});
+		}
+
+		for (name, schedule) in self.schedule.iter() {
+			let section = format!("schedule.{name}");
+
+			if !self.urls.contains_key(name) {
+				problems.push(Problem::section(section, "unknown url"));
+			} else if let Err(problem) = schedule.parse() {
+				problems.push(Problem { section, ..problem });
+			}
+		}
+
+		if let DisplayPower::Command { sleep, resume } = &self.display {
+			for (name, command) in [("sleep", sleep), ("resume", resume)] {
+				if command.is_empty() {
+					problems.push(Problem::new("display", name, "empty command"));
+				}
+			}
+		}
+
+		problems
+	}
+}
+
+impl ScheduleSection {
+	const TIME_FORMAT: &str = "%H:%M";
+
+	fn parse(&self) -> Result<Schedule, Problem> {
+		Ok(Schedule {
+			days: self
+				.days
+				.iter()
+				.map(|day| {
+					day.parse().map_err(|_| {
+						Problem::new("schedule", "days", format!("invalid day: {day:?}"))
 					})
 				})
-				.unwrap_or(default_s),
-		)
+				.collect::<Result<_, _>>()?,
+			times: self
+				.times
+				.iter()
+				.map(|times| {
+					times
+						.split_once("-")
+						.and_then(|(start, end)| {
+							Some((
+								NaiveTime::parse_from_str(start.trim(), Self::TIME_FORMAT).ok()?,
+								NaiveTime::parse_from_str(end.trim(), Self::TIME_FORMAT).ok()?,
+							))
+						})
+						.ok_or(Problem::new(
+							"schedule",
+							"times",
+							format!("invalid time range (expected HH:MM-HH:MM): {times:?}"),
+						))
+				})
+				.collect::<Result<_, _>>()?,
+			space_open: self.space_open,
+		})
+	}
+}
+
+impl DeviceSection {
+	/* ABS_HAT3Y */
+	const HAT_LAST: u16 = 3;
+	/* KEY_MAX */
+	const KEY_LAST: u16 = 0x2ff;
+
+	fn parse(&self, name: &str) -> Result<Mapping, Problem> {
+		let action = |section: &str, key: &str, value: &str| {
+			value
+				.parse::<Action>()
+				.map_err(|err| Problem::new(section, key, err))
+		};
+		let optional = |section: &str, key: &str, value: &Option<String>| {
+			value
+				.as_deref()
+				.map(|value| action(section, key, value))
+				.transpose()
+		};
+		let section = format!("devices.{name}");
+
+		if self.long_press == 0 {
+			return Err(Problem::new(&section, "long_press", "invalid value: 0"));
+		}
+
+		if self.double_press == 0 {
+			return Err(Problem::new(&section, "double_press", "invalid value: 0"));
+		}
+
+		if let Some(code) = self.passthrough.iter().find(|code| **code > Self::KEY_LAST) {
+			return Err(Problem::new(
+				&section,
+				"passthrough",
+				format!("invalid key code: {code}"),
+			));
+		}
+
+		let mut mapping = Mapping {
+			long: IndexMap::new(),
+			double: IndexMap::new(),
+			chords: IndexMap::new(),
+			long_press: Duration::from_millis(self.long_press),
+			double_press: Duration::from_millis(self.double_press),
+			passthrough: self.passthrough.clone(),
+			..Mapping::default()
+		};
+
+		/* Keep the default bindings if only the options of the device are configured */
+		if !self.keys.is_empty() || !self.axes.is_empty() || !self.hats.is_empty() {
+			mapping.keys.clear();
+			mapping.axes.clear();
+		}
+
+		for (keys, bindings, actions) in [
+			("keys", &self.keys, &mut mapping.keys),
+			("long", &self.long, &mut mapping.long),
+			("double", &self.double, &mut mapping.double),
+		] {
+			let section = format!("devices.{name}.{keys}");
+
+			for (code, value) in bindings.iter() {
+				actions.insert(*code, action(&section, &code.to_string(), value)?);
+			}
+		}
+
+		for (keys, value) in self.chords.iter() {
+			let section = format!("devices.{name}.chords");
+			let codes = keys
+				.split_once('+')
+				.and_then(|(a, b)| {
+					Some((a.trim().parse::<u16>().ok()?, b.trim().parse::<u16>().ok()?))
+				})
+				.filter(|(a, b)| a != b)
+				.ok_or_else(|| {
+					Problem::new(&section, keys, "expected two key codes (\"288+289\")")
+				})?;
+
+			mapping.chords.insert(
+				(codes.0.min(codes.1), codes.0.max(codes.1)),
+				action(&section, keys, value)?,
+			);
+		}
+
+		for (code, axis) in self.axes.iter() {
+			let section = format!("devices.{name}.axes.{code}");
+
+			if axis.threshold <= 0 {
+				return Err(Problem::new(
+					section,
+					"threshold",
+					format!("invalid value: {}", axis.threshold),
+				));
+			}
+
+			mapping.axes.insert(
+				*code,
+				Axis {
+					centre: axis.centre,
+					threshold: axis.threshold,
+					low: optional(&section, "low", &axis.low)?,
+					high: optional(&section, "high", &axis.high)?,
+				},
+			);
+		}
+
+		for (hat, directions) in self.hats.iter() {
+			let section = format!("devices.{name}.hats.{hat}");
+
+			if *hat > Self::HAT_LAST {
+				return Err(Problem::section(section, "invalid hat switch"));
+			}
+
+			let (x, y) = Mapping::hat_axes(*hat);
+			let axis = |low, high| Axis {
+				centre: 0,
+				threshold: 1,
+				low,
+				high,
+			};
+
+			mapping.axes.insert(
+				x,
+				axis(
+					optional(&section, "left", &directions.left)?,
+					optional(&section, "right", &directions.right)?,
+				),
+			);
+			mapping.axes.insert(
+				y,
+				axis(
+					optional(&section, "up", &directions.up)?,
+					optional(&section, "down", &directions.down)?,
+				),
+			);
+		}
+
+		Ok(mapping)
+	}
+}
+
+impl SequenceSection {
+	fn parse(&self, name: &str) -> Result<Sequence, Problem> {
+		let section = format!("sequences.{name}");
+		let action = |key: &str, value: &str| {
+			value
+				.parse::<Action>()
+				.map_err(|err| Problem::new(&section, key, err))
+		};
+
+		if self.inputs.is_empty() {
+			return Err(Problem::new(&section, "inputs", "no inputs"));
+		}
+
+		if self.timeout == 0 {
+			return Err(Problem::new(&section, "timeout", "invalid value: 0"));
+		}
+
+		Ok(Sequence {
+			name: name.to_owned(),
+			inputs: self
+				.inputs
+				.iter()
+				.map(|input| action("inputs", input))
+				.collect::<Result<_, _>>()?,
+			action: action("action", &self.action)?,
+			timeout: Duration::from_secs(self.timeout),
+		})
+	}
+}
+
+impl Schedule {
+	/// Check if the page should be shown at this time
+	pub fn active(&self, now: NaiveDateTime, space_open: bool) -> bool {
+		let time = now.time();
+
+		(self.days.is_empty() || self.days.contains(&now.weekday()))
+			&& (self.times.is_empty()
+				|| self.times.iter().any(|&(start, end)| {
+					if start <= end {
+						start <= time && time < end
+					} else {
+						start <= time || time < end
+					}
+				})) && self
+			.space_open
+			.is_none_or(|required| required == space_open)
+	}
+}
+
+impl MqttTopics {
+	/// State topic of the status screen
+	pub fn state(&self, name: &str) -> String {
+		format!("{}/{name}", self.prefix)
+	}
+
+	/// Command topic of the status screen
+	pub fn command(&self, name: &str) -> String {
+		format!("{}/cmd/{name}", self.prefix)
+	}
+
+	pub fn availability(&self) -> String {
+		self.state("availability")
+	}
+}
+
+impl ClipsSection {
+	pub fn directory(&self) -> Option<PathBuf> {
+		self.directory
+			.clone()
+			.or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join("clips")))
+	}
+}
+
+impl PresencePayload {
+	/// Check if the payload means that the space is occupied, or return
+	/// None if it isn't recognised
+	pub fn present(&self, payload: &[u8]) -> Option<bool> {
+		let value = match &self.json_pointer {
+			Some(pointer) => {
+				match serde_json::from_slice::<serde_json::Value>(payload)
+					.ok()?
+					.pointer(pointer)?
+				{
+					serde_json::Value::String(value) => value.clone(),
+					value => value.to_string(),
+				}
+			}
+			None => String::from_utf8_lossy(payload).into_owned(),
+		};
+
+		if self.empty.contains(&value) {
+			Some(false)
+		} else if self.present.is_empty() || self.present.contains(&value) {
+			Some(true)
+		} else {
+			None
+		}
+	}
+}
+
+impl Problem {
+	fn new<S: ToString, K: ToString, M: ToString>(section: S, key: K, message: M) -> Self {
+		Self {
+			section: section.to_string(),
+			key: Some(key.to_string()),
+			message: message.to_string(),
+		}
+	}
+
+	fn section<S: ToString, M: ToString>(section: S, message: M) -> Self {
+		Self {
+			section: section.to_string(),
+			key: None,
+			message: message.to_string(),
+		}
+	}
+
+	/// Find the line of the config file that this problem refers to
+	fn line(&self, source: &str) -> Option<usize> {
+		let mut in_section = false;
+		let mut section_line = None;
+
+		for (number, line) in source
+			.lines()
+			.enumerate()
+			.map(|(i, line)| (i + 1, line.trim()))
+		{
+			if let Some(name) = line
+				.strip_prefix("[")
+				.and_then(|line| line.strip_suffix("]"))
+			{
+				in_section = name.trim() == self.section;
+				if in_section && section_line.is_none() {
+					section_line = Some(number);
+				}
+			} else if in_section
+				&& let (Some(key), Some((name, _))) = (&self.key, line.split_once("="))
+				&& name.trim().trim_matches('"') == key
+			{
+				return Some(number);
+			}
+		}
+
+		section_line
+	}
+}
+
+impl fmt::Display for Problem {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match &self.key {
+			Some(key) => write!(f, "[{}] {key}: {}", self.section, self.message),
+			None => write!(f, "[{}]: {}", self.section, self.message),
+		}
+	}
+}
+
+impl Default for SleepSection {
+	fn default() -> Self {
+		Self {
+			delay: 0,
+			awake: 60,
+			idle: None,
+			never: None,
+		}
+	}
+}
+
+impl Default for ClipsSection {
+	fn default() -> Self {
+		Self {
+			directory: None,
+			command: vec!["mpv".to_owned()],
+			display: ":0".to_owned(),
+			queue: 10,
+		}
+	}
+}
+
+impl Default for SequenceSection {
+	fn default() -> Self {
+		Self {
+			keyboard: "main".to_owned(),
+			inputs: Vec::new(),
+			action: String::new(),
+			timeout: 2,
+		}
+	}
+}
+
+impl Default for DeviceSection {
+	fn default() -> Self {
+		Self {
+			keys: IndexMap::new(),
+			axes: IndexMap::new(),
+			hats: IndexMap::new(),
+			long: IndexMap::new(),
+			double: IndexMap::new(),
+			chords: IndexMap::new(),
+			long_press: Mapping::LONG_PRESS.as_millis() as u64,
+			double_press: Mapping::DOUBLE_PRESS.as_millis() as u64,
+			grab: false,
+			passthrough: Vec::new(),
+		}
+	}
+}
+
+impl Default for AxisSection {
+	fn default() -> Self {
+		Self {
+			centre: 0,
+			threshold: 1,
+			low: None,
+			high: None,
+		}
+	}
+}
+
+impl Default for MqttSection {
+	fn default() -> Self {
+		Self {
+			hostname: String::new(),
+			port: 1883,
+			client_id: "status-screen".to_owned(),
+			username: None,
+			password: None,
+			tls_ca: None,
+			keep_alive: 60,
+			topics: MqttTopics::default(),
+			presence: PresencePayload::default(),
+		}
+	}
+}
+
+impl Default for MqttTopics {
+	fn default() -> Self {
+		Self {
+			prefix: "status-screen".to_owned(),
+			presence: "sensor/global/presence".to_owned(),
+			clip: "clip".to_owned(),
+			timer_reset: "time-since-last/reset".to_owned(),
+		}
+	}
+}
+
+impl Default for PresencePayload {
+	fn default() -> Self {
+		Self {
+			json_pointer: None,
+			empty: vec!["empty".to_owned()],
+			present: Vec::new(),
+		}
+	}
+}
+
+impl Default for HomeAssistantSection {
+	fn default() -> Self {
+		Self {
+			discovery_prefix: "homeassistant".to_owned(),
+		}
+	}
+}
+
+impl Default for AutoscrollSection {
+	fn default() -> Self {
+		Self {
+			delay: 20,
+			hold: 60,
+			pause: 900,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use chrono::{NaiveDate, NaiveDateTime};
+
+	use indexmap::IndexMap;
+
+	use crate::mapping::{Action, Mapping};
+
+	use super::{DeviceSection, PresencePayload, Problem, ScheduleSection, State};
+
+	const MINIMAL: &str = r#"
+[keyboards]
+main = "/dev/input/event0"
+
+[urls]
+buses = "http://localhost:5000"
+"#;
+
+	/// Problems found in a config file (without the minimal config)
+	fn check(source: &str) -> Vec<String> {
+		config::Config::builder()
+			.add_source(config::File::from_str(
+				&format!("{MINIMAL}{source}"),
+				config::FileFormat::Toml,
+			))
+			.build()
+			.unwrap()
+			.try_deserialize::<State>()
+			.unwrap()
+			.check()
+			.iter()
+			.map(Problem::to_string)
+			.collect()
+	}
+
+	fn time(day: u32, hour: u32, min: u32) -> NaiveDateTime {
+		/* 2025-06-02 is a Monday */
+		NaiveDate::from_ymd_opt(2025, 6, day)
+			.unwrap()
+			.and_hms_opt(hour, min, 0)
+			.unwrap()
+	}
+
+	#[test]
+	fn check_minimal() {
+		assert!(check("").is_empty());
+	}
+
+	#[test]
+	fn check_unknown_names() {
+		assert_eq!(
+			check(
+				r#"
+[tabs]
+0 = "buses"
+1 = "trains"
+2 = "!./life.sh"
+3 = "!"
+
+[reload]
+trains = 60
+
+[sequences.konami]
+keyboard = "tabs"
+inputs = ["dpad up"]
+action = "next"
+"#
+			),
+			[
+				"[sequences.konami] keyboard: keyboard not configured: \"tabs\"",
+				"[reload] trains: unknown url",
+				"[tabs] 1: unknown url: trains",
+				"[tabs] 3: empty command",
+			]
+		);
+	}
+
+	#[test]
+	fn check_bad_keys() {
+		assert_eq!(
+			check(
+				r#"
+[devices.main.keys]
+304 = "jump"
+
+[devices.other.chords]
+"288+288" = "next"
+"#
+			),
+			[
+				"[devices.main.keys] 304: invalid action: \"jump\"",
+				"[devices.other]: keyboard not configured",
+				"[devices.other.chords] 288+288: expected two key codes (\"288+289\")",
+			]
+		);
+	}
+
+	#[test]
+	fn check_values() {
+		assert_eq!(
+			check(
+				r#"
+[mqtt]
+hostname = ""
+password = "secret"
+
+[mqtt.topics]
+prefix = "status-screen/#"
+
+[autoscroll]
+delay = 0
+hold = 30
+
+[http]
+listen = "localhost"
+
+[devices.main]
+passthrough = [28]
+"#
+			),
+			[
+				"[devices.main] passthrough: device not grabbed",
+				"[mqtt] hostname: empty hostname",
+				"[mqtt] password: password without username",
+				"[mqtt.topics] prefix: invalid topic: \"status-screen/#\"",
+				"[http] listen: invalid address: \"localhost\"",
+				"[autoscroll] delay: invalid value: 0",
+			]
+		);
+	}
+
+	#[test]
+	fn problem_line() {
+		let source = format!(
+			"{MINIMAL}{}",
+			r#"
+[tabs]
+0 = "buses"
+"1" = "trains"
+
+[devices.main.keys]
+304 = "jump"
+"#
+		);
+
+		assert_eq!(
+			Problem::new("tabs", 1, "unknown url").line(&source),
+			Some(10)
+		);
+		assert_eq!(
+			Problem::new("devices.main.keys", 304, "").line(&source),
+			Some(13)
+		);
+		assert_eq!(Problem::new("urls", "trains", "").line(&source), Some(5));
+		assert_eq!(Problem::section("urls", "no urls").line(&source), Some(5));
+		assert_eq!(Problem::section("mqtt", "no hostname").line(&source), None);
+	}
+
+	#[test]
+	fn device_options() {
+		let options = DeviceSection {
+			grab: true,
+			passthrough: vec![30, 48],
+			..DeviceSection::default()
+		};
+		let mapping = options.parse("main").unwrap();
+
+		assert_eq!(mapping.keys, Mapping::default().keys);
+		assert_eq!(mapping.axes, Mapping::default().axes);
+		assert_eq!(mapping.passthrough, [30, 48]);
+
+		let mapping = DeviceSection {
+			keys: IndexMap::from([(30, "next".to_owned())]),
+			..options.clone()
+		}
+		.parse("main")
+		.unwrap();
+
+		assert_eq!(mapping.keys, IndexMap::from([(30, Action::Next)]));
+		assert!(mapping.axes.is_empty());
+
+		assert_eq!(
+			DeviceSection {
+				passthrough: vec![0x300],
+				..options
+			}
+			.parse("main")
+			.unwrap_err()
+			.to_string(),
+			"[devices.main] passthrough: invalid key code: 768"
+		);
+	}
+
+	#[test]
+	fn schedule_days_and_times() {
+		let schedule = ScheduleSection {
+			days: vec!["Sat".to_owned(), "sunday".to_owned()],
+			times: vec!["10:00-12:00".to_owned(), "22:00-02:00".to_owned()],
+			space_open: None,
+		}
+		.parse()
+		.unwrap();
+
+		assert!(schedule.active(time(7, 10, 0), true));
+		assert!(schedule.active(time(8, 11, 59), false));
+		assert!(!schedule.active(time(7, 12, 0), true));
+		assert!(schedule.active(time(7, 23, 0), true));
+		assert!(schedule.active(time(8, 1, 0), true));
+		assert!(!schedule.active(time(2, 11, 0), true));
+	}
+
+	#[test]
+	fn schedule_space_open() {
+		let schedule = |space_open| {
+			ScheduleSection {
+				space_open,
+				..Default::default()
+			}
+			.parse()
+			.unwrap()
+		};
+
+		assert!(schedule(None).active(time(2, 3, 0), false));
+		assert!(schedule(Some(true)).active(time(2, 3, 0), true));
+		assert!(!schedule(Some(true)).active(time(2, 3, 0), false));
+		assert!(schedule(Some(false)).active(time(2, 3, 0), false));
+	}
+
+	#[test]
+	fn schedule_errors() {
+		let parse = |days: &[&str], times: &[&str]| {
+			ScheduleSection {
+				days: days.iter().map(|day| day.to_string()).collect(),
+				times: times.iter().map(|times| times.to_string()).collect(),
+				space_open: None,
+			}
+			.parse()
+			.map_err(|problem| problem.to_string())
+		};
+
+		assert!(parse(&["Mon"], &["07:00-23:00"]).is_ok());
+		assert_eq!(
+			parse(&["Caturday"], &[]).unwrap_err(),
+			"[schedule] days: invalid day: \"Caturday\""
+		);
+		assert!(parse(&[], &["07:00"]).is_err());
+		assert!(parse(&[], &["7am-11pm"]).is_err());
+		assert!(parse(&[], &["25:00-26:00"]).is_err());
+	}
+
+	#[test]
+	fn presence_payload() {
+		let presence = PresencePayload::default();
+
+		assert_eq!(presence.present(b"empty"), Some(false));
+		assert_eq!(presence.present(b"occupied"), Some(true));
+
+		let presence = PresencePayload {
+			json_pointer: Some("/state/occupancy".to_owned()),
+			empty: vec!["false".to_owned()],
+			present: vec!["true".to_owned()],
+		};
+
+		assert_eq!(
+			presence.present(br#"{"state": {"occupancy": false}}"#),
+			Some(false)
+		);
+		assert_eq!(
+			presence.present(br#"{"state": {"occupancy": true}}"#),
+			Some(true)
+		);
+		assert_eq!(
+			presence.present(br#"{"state": {"occupancy": "unknown"}}"#),
+			None
+		);
+		assert_eq!(presence.present(br#"{"state": {}}"#), None);
+		assert_eq!(presence.present(b"not json"), None);
 	}
 }
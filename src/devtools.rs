@@ -0,0 +1,629 @@
+/*
+ * Copyright 2025  Simon Arlott
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use std::{
+	collections::HashMap,
+	io::{self, BufRead, BufReader},
+	net::TcpStream,
+	process::{Child, Command, Stdio},
+	sync::{Mutex, mpsc},
+	thread,
+	time::Duration,
+};
+
+use anyhow::{Error, anyhow};
+use log::{debug, trace};
+use serde_json::{Value, json};
+use tungstenite::{Message, WebSocket, stream::MaybeTlsStream};
+
+/// Chrome DevTools Protocol client for the browser
+#[derive(derive_more::Debug)]
+pub struct DevTools {
+	#[debug(skip)]
+	socket: Mutex<Socket>,
+	state: Mutex<DevToolsState>,
+}
+
+#[derive(Debug)]
+struct Socket {
+	websocket: WebSocket<MaybeTlsStream<TcpStream>>,
+	next_id: u64,
+}
+
+#[derive(Debug, Default)]
+struct DevToolsState {
+	/// Target ids of the tabs, in order
+	targets: Vec<String>,
+	/// Session ids of attached targets
+	sessions: HashMap<String, String>,
+}
+
+impl DevTools {
+	/* Input.dispatchKeyEvent modifiers */
+	const MODIFIER_ALT: u64 = 1;
+	const MODIFIER_CTRL: u64 = 2;
+	const MODIFIER_META: u64 = 4;
+	const MODIFIER_SHIFT: u64 = 8;
+
+	/// Maximum time to wait for the response to a command
+	const CALL_TIMEOUT: Duration = Duration::from_secs(10);
+
+	/// Start the browser with remote debugging enabled and connect to it
+	pub fn launch(mut command: Command, port: u16) -> Result<(Child, Self), Error> {
+		const LISTENING: &str = "DevTools listening on ";
+		const TIMEOUT: Duration = Duration::from_secs(30);
+
+		let mut child = command
+			.arg(format!("--remote-debugging-port={port}"))
+			.stderr(Stdio::piped())
+			.spawn()?;
+		let stderr = child
+			.stderr
+			.take()
+			.ok_or(anyhow!("No stderr for browser"))?;
+		let (tx, rx) = mpsc::channel();
+
+		thread::spawn(move || {
+			for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+				match line.strip_prefix(LISTENING) {
+					Some(url) => {
+						let _ = tx.send(url.to_owned());
+					}
+					None => trace!("Browser: {line}"),
+				}
+			}
+		});
+
+		let url = rx
+			.recv_timeout(TIMEOUT)
+			.map_err(|_| anyhow!("Browser did not start remote debugging"))?;
+
+		Ok((child, Self::connect(&url)?))
+	}
+
+	/// Connect to the browser target at a DevTools WebSocket url
+	pub fn connect(url: &str) -> Result<Self, Error> {
+		let (websocket, _) = tungstenite::connect(url)?;
+
+		/* Commands are sent while holding locks so they can't wait forever */
+		if let MaybeTlsStream::Plain(stream) = websocket.get_ref() {
+			stream.set_read_timeout(Some(Self::CALL_TIMEOUT))?;
+		}
+
+		debug!("Connected to DevTools at {url}");
+
+		Ok(Self {
+			socket: Mutex::new(Socket {
+				websocket,
+				next_id: 1,
+			}),
+			state: Mutex::new(DevToolsState::default()),
+		})
+	}
+
+	/// Replace all existing pages with new tabs for the urls
+	pub fn open_all(&self, urls: &[String]) -> Result<(), Error> {
+		let existing = self.call(None, "Target.getTargets", json!({}))?;
+
+		for url in urls {
+			self.open(url)?;
+		}
+
+		for target in existing["targetInfos"].as_array().into_iter().flatten() {
+			if target["type"] == "page"
+				&& let Some(target_id) = target["targetId"].as_str()
+			{
+				self.call(None, "Target.closeTarget", json!({ "targetId": target_id }))?;
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Open a new tab at the end
+	pub fn open(&self, url: &str) -> Result<(), Error> {
+		let result = self.call(None, "Target.createTarget", json!({ "url": url }))?;
+		let target_id = result["targetId"]
+			.as_str()
+			.ok_or(anyhow!("No target id for new tab"))?;
+
+		self.state
+			.lock()
+			.unwrap()
+			.targets
+			.push(target_id.to_owned());
+		Ok(())
+	}
+
+	pub fn close(&self, index: usize) -> Result<(), Error> {
+		let target_id = self.target(index)?;
+
+		self.call(None, "Target.closeTarget", json!({ "targetId": target_id }))?;
+
+		let mut state = self.state.lock().unwrap();
+
+		state.targets.remove(index);
+		state.sessions.remove(&target_id);
+		Ok(())
+	}
+
+	/// Change the position of a tab (tabs are activated by id so the order
+	/// of the tab strip doesn't matter)
+	pub fn move_tab(&self, from: usize, to: usize) -> Result<(), Error> {
+		let mut state = self.state.lock().unwrap();
+
+		if from >= state.targets.len() || to >= state.targets.len() {
+			return Err(anyhow!("Invalid tab move from {from} to {to}"));
+		}
+
+		let target_id = state.targets.remove(from);
+		state.targets.insert(to, target_id);
+		Ok(())
+	}
+
+	pub fn activate(&self, index: usize) -> Result<(), Error> {
+		let target_id = self.target(index)?;
+
+		self.call(
+			None,
+			"Target.activateTarget",
+			json!({ "targetId": target_id }),
+		)?;
+		Ok(())
+	}
+
+	pub fn reload(&self, index: usize) -> Result<(), Error> {
+		let session_id = self.session(index)?;
+
+		self.call(Some(&session_id), "Page.reload", json!({}))?;
+		Ok(())
+	}
+
+	pub fn navigate(&self, index: usize, url: &str) -> Result<(), Error> {
+		let session_id = self.session(index)?;
+
+		self.call(Some(&session_id), "Page.navigate", json!({ "url": url }))?;
+		Ok(())
+	}
+
+	/// Current url of a tab
+	pub fn url(&self, index: usize) -> Result<String, Error> {
+		let target_id = self.target(index)?;
+		let result = self.call(
+			None,
+			"Target.getTargetInfo",
+			json!({ "targetId": target_id }),
+		)?;
+
+		Ok(result["targetInfo"]["url"]
+			.as_str()
+			.ok_or(anyhow!("No url for tab {index}"))?
+			.to_owned())
+	}
+
+	/// Press keys on a tab using the same format as xdotool
+	/// (e.g. "Ctrl+Shift+Tab Down x")
+	pub fn press(&self, index: usize, keys: &str) -> Result<(), Error> {
+		let session_id = self.session(index)?;
+
+		for combination in keys.split_whitespace() {
+			let mut modifiers = 0;
+			let mut parts: Vec<&str> = combination.split("+").collect();
+			let key = parts.pop().ok_or(anyhow!("Empty key"))?;
+
+			for modifier in parts {
+				modifiers |= match modifier {
+					"Alt" => Self::MODIFIER_ALT,
+					"Ctrl" | "Control" => Self::MODIFIER_CTRL,
+					"Super" | "Meta" => Self::MODIFIER_META,
+					"Shift" => Self::MODIFIER_SHIFT,
+					_ => return Err(anyhow!("Unknown modifier {modifier:?} in {combination:?}")),
+				};
+			}
+
+			let mut event = Self::key_event(key)?;
+
+			/* Shortcuts don't type any text */
+			if modifiers & !Self::MODIFIER_SHIFT != 0
+				&& let Some(event) = event.as_object_mut()
+			{
+				event.remove("text");
+			}
+
+			event["modifiers"] = modifiers.into();
+			event["type"] = if event.get("text").is_some() {
+				"keyDown"
+			} else {
+				"rawKeyDown"
+			}
+			.into();
+			self.call(Some(&session_id), "Input.dispatchKeyEvent", event.clone())?;
+
+			event["type"] = "keyUp".into();
+			self.call(Some(&session_id), "Input.dispatchKeyEvent", event)?;
+		}
+
+		Ok(())
+	}
+
+	/// Convert an xdotool key name to Input.dispatchKeyEvent parameters
+	fn key_event(key: &str) -> Result<Value, Error> {
+		let named = |key: &str, code: u64| json!({ "key": key, "code": key, "windowsVirtualKeyCode": code });
+
+		Ok(match key {
+			"Up" => named("ArrowUp", 38),
+			"Down" => named("ArrowDown", 40),
+			"Left" => named("ArrowLeft", 37),
+			"Right" => named("ArrowRight", 39),
+			"Prior" | "Page_Up" => named("PageUp", 33),
+			"Next" | "Page_Down" => named("PageDown", 34),
+			"Home" => named("Home", 36),
+			"End" => named("End", 35),
+			"Tab" => named("Tab", 9),
+			"Escape" => named("Escape", 27),
+			"BackSpace" => named("Backspace", 8),
+			"Return" => {
+				json!({ "key": "Enter", "code": "Enter", "windowsVirtualKeyCode": 13, "text": "\r" })
+			}
+			"space" => {
+				json!({ "key": " ", "code": "Space", "windowsVirtualKeyCode": 32, "text": " " })
+			}
+			_ => {
+				let mut chars = key.chars();
+
+				match (chars.next(), chars.next()) {
+					(Some(c), None) if c.is_ascii_alphanumeric() => json!({
+						"key": key,
+						"code": if c.is_ascii_digit() {
+							format!("Digit{c}")
+						} else {
+							format!("Key{}", c.to_ascii_uppercase())
+						},
+						"windowsVirtualKeyCode": c.to_ascii_uppercase() as u64,
+						"text": key,
+					}),
+					_ => return Err(anyhow!("Unknown key {key:?}")),
+				}
+			}
+		})
+	}
+
+	fn target(&self, index: usize) -> Result<String, Error> {
+		self.state
+			.lock()
+			.unwrap()
+			.targets
+			.get(index)
+			.cloned()
+			.ok_or(anyhow!("No target for tab {index}"))
+	}
+
+	/// Attach to a tab to send it page-level commands
+	fn session(&self, index: usize) -> Result<String, Error> {
+		let target_id = self.target(index)?;
+
+		if let Some(session_id) = self.state.lock().unwrap().sessions.get(&target_id) {
+			return Ok(session_id.clone());
+		}
+
+		let result = self.call(
+			None,
+			"Target.attachToTarget",
+			json!({ "targetId": target_id, "flatten": true }),
+		)?;
+		let session_id = result["sessionId"]
+			.as_str()
+			.ok_or(anyhow!("No session id for tab {index}"))?
+			.to_owned();
+
+		self.state
+			.lock()
+			.unwrap()
+			.sessions
+			.insert(target_id, session_id.clone());
+		Ok(session_id)
+	}
+
+	/// Send a command and wait for its result, ignoring any events
+	fn call(&self, session_id: Option<&str>, method: &str, params: Value) -> Result<Value, Error> {
+		let mut socket = self.socket.lock().unwrap();
+		let id = socket.next_id;
+		let mut message = json!({ "id": id, "method": method, "params": params });
+
+		socket.next_id += 1;
+		if let Some(session_id) = session_id {
+			message["sessionId"] = session_id.into();
+		}
+
+		trace!("DevTools send: {message}");
+		socket.websocket.send(Message::text(message.to_string()))?;
+
+		loop {
+			let message = match socket.websocket.read() {
+				Err(tungstenite::Error::Io(err))
+					if matches!(
+						err.kind(),
+						io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+					) =>
+				{
+					return Err(anyhow!("DevTools {method} timed out"));
+				}
+				result => result?,
+			};
+
+			if !message.is_text() {
+				continue;
+			}
+
+			let mut response: Value = serde_json::from_str(message.to_text()?)?;
+
+			if response["id"] != id {
+				trace!("DevTools event: {response}");
+				continue;
+			}
+
+			trace!("DevTools receive: {response}");
+
+			if let Some(error) = response.get("error") {
+				return Err(anyhow!("DevTools {method} failed: {}", error["message"]));
+			}
+
+			return Ok(response["result"].take());
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{
+		net::TcpListener,
+		sync::{Arc, Mutex},
+		thread,
+		time::Duration,
+	};
+
+	use serde_json::{Value, json};
+	use tungstenite::{Message, stream::MaybeTlsStream};
+
+	use super::DevTools;
+
+	/// Minimal stand-in for the browser end of the DevTools protocol
+	fn server(received: Arc<Mutex<Vec<Value>>>) -> String {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let url = format!(
+			"ws://{}/devtools/browser/test",
+			listener.local_addr().unwrap()
+		);
+
+		thread::spawn(move || {
+			let (stream, _) = listener.accept().unwrap();
+			let mut websocket = tungstenite::accept(stream).unwrap();
+			let mut targets = vec![("initial".to_owned(), "chrome://newtab/".to_owned())];
+
+			while let Ok(message) = websocket.read() {
+				if !message.is_text() {
+					continue;
+				}
+
+				let request: Value = serde_json::from_str(message.to_text().unwrap()).unwrap();
+				let params = &request["params"];
+				let result = match request["method"].as_str().unwrap() {
+					"Target.getTargets" => json!({
+						"targetInfos": targets.iter().map(|(id, url)| json!({
+							"targetId": id, "type": "page", "url": url,
+						})).collect::<Vec<_>>(),
+					}),
+					"Target.createTarget" => {
+						let id = format!("T{}", targets.len());
+						targets.push((id.clone(), params["url"].as_str().unwrap().to_owned()));
+						json!({ "targetId": id })
+					}
+					"Target.closeTarget" => {
+						targets.retain(|(id, _)| *id != params["targetId"]);
+						json!({ "success": true })
+					}
+					"Target.attachToTarget" => {
+						websocket
+							.send(Message::text(
+								json!({
+									"method": "Target.attachedToTarget",
+									"params": { "targetInfo": { "targetId": params["targetId"] } },
+								})
+								.to_string(),
+							))
+							.unwrap();
+						json!({ "sessionId": format!("S-{}", params["targetId"].as_str().unwrap()) })
+					}
+					"Target.getTargetInfo" => {
+						let (id, url) = targets
+							.iter()
+							.find(|(id, _)| *id == params["targetId"])
+							.unwrap();
+						json!({ "targetInfo": { "targetId": id, "url": url } })
+					}
+					"Page.navigate" => {
+						let id = request["sessionId"]
+							.as_str()
+							.unwrap()
+							.strip_prefix("S-")
+							.unwrap();
+						targets
+							.iter_mut()
+							.find(|(target_id, _)| target_id == id)
+							.unwrap()
+							.1 = params["url"].as_str().unwrap().to_owned();
+						json!({ "frameId": "F" })
+					}
+					"Slow.method" => continue,
+					"Unknown.method" => {
+						websocket
+							.send(Message::text(
+								json!({ "id": request["id"], "error": { "code": -32601, "message": "not found" } })
+									.to_string(),
+							))
+							.unwrap();
+						received.lock().unwrap().push(request);
+						continue;
+					}
+					_ => json!({}),
+				};
+
+				received.lock().unwrap().push(request.clone());
+				websocket
+					.send(Message::text(
+						json!({ "id": request["id"], "result": result }).to_string(),
+					))
+					.unwrap();
+			}
+		});
+
+		url
+	}
+
+	fn methods(received: &Arc<Mutex<Vec<Value>>>) -> Vec<String> {
+		received
+			.lock()
+			.unwrap()
+			.iter()
+			.map(|request| request["method"].as_str().unwrap().to_owned())
+			.collect()
+	}
+
+	#[test]
+	fn open_all_replaces_initial_tab() {
+		let received = Arc::new(Mutex::new(Vec::new()));
+		let devtools = DevTools::connect(&server(received.clone())).unwrap();
+
+		devtools
+			.open_all(&["http://a/".to_owned(), "http://b/".to_owned()])
+			.unwrap();
+
+		assert_eq!(
+			methods(&received),
+			[
+				"Target.getTargets",
+				"Target.createTarget",
+				"Target.createTarget",
+				"Target.closeTarget",
+			]
+		);
+		assert_eq!(received.lock().unwrap()[3]["params"]["targetId"], "initial");
+		assert_eq!(devtools.url(0).unwrap(), "http://a/");
+		assert_eq!(devtools.url(1).unwrap(), "http://b/");
+	}
+
+	#[test]
+	fn navigate_and_reload_use_one_session() {
+		let received = Arc::new(Mutex::new(Vec::new()));
+		let devtools = DevTools::connect(&server(received.clone())).unwrap();
+
+		devtools.open("http://a/").unwrap();
+		devtools.navigate(0, "http://c/").unwrap();
+		devtools.reload(0).unwrap();
+		devtools.activate(0).unwrap();
+
+		assert_eq!(devtools.url(0).unwrap(), "http://c/");
+		assert_eq!(
+			methods(&received),
+			[
+				"Target.createTarget",
+				"Target.attachToTarget",
+				"Page.navigate",
+				"Page.reload",
+				"Target.activateTarget",
+				"Target.getTargetInfo",
+			]
+		);
+		assert_eq!(received.lock().unwrap()[3]["sessionId"], "S-T1");
+	}
+
+	#[test]
+	fn move_and_close_tabs() {
+		let received = Arc::new(Mutex::new(Vec::new()));
+		let devtools = DevTools::connect(&server(received.clone())).unwrap();
+
+		devtools.open("http://a/").unwrap();
+		devtools.open("http://b/").unwrap();
+		devtools.move_tab(1, 0).unwrap();
+		assert_eq!(devtools.url(0).unwrap(), "http://b/");
+
+		devtools.close(0).unwrap();
+		assert_eq!(devtools.url(0).unwrap(), "http://a/");
+		assert!(devtools.activate(1).is_err());
+		assert!(devtools.move_tab(0, 1).is_err());
+	}
+
+	#[test]
+	fn press_keys() {
+		let received = Arc::new(Mutex::new(Vec::new()));
+		let devtools = DevTools::connect(&server(received.clone())).unwrap();
+
+		devtools.open("http://a/").unwrap();
+		devtools.press(0, "x Ctrl+Shift+Tab Up").unwrap();
+		assert!(devtools.press(0, "Hyper+x").is_err());
+		assert!(devtools.press(0, "F13").is_err());
+
+		let events: Vec<Value> = received
+			.lock()
+			.unwrap()
+			.iter()
+			.filter(|request| request["method"] == "Input.dispatchKeyEvent")
+			.map(|request| request["params"].clone())
+			.collect();
+
+		assert_eq!(events.len(), 6);
+		assert_eq!(events[0]["type"], "keyDown");
+		assert_eq!(events[0]["text"], "x");
+		assert_eq!(events[1]["type"], "keyUp");
+		assert_eq!(events[2]["type"], "rawKeyDown");
+		assert_eq!(events[2]["key"], "Tab");
+		assert_eq!(events[2]["modifiers"], 10);
+		assert_eq!(events[4]["key"], "ArrowUp");
+		assert_eq!(events[4]["windowsVirtualKeyCode"], 38);
+	}
+
+	#[test]
+	fn command_errors() {
+		let received = Arc::new(Mutex::new(Vec::new()));
+		let devtools = DevTools::connect(&server(received.clone())).unwrap();
+
+		assert!(devtools.call(None, "Unknown.method", json!({})).is_err());
+		assert!(devtools.url(0).is_err());
+	}
+
+	#[test]
+	fn command_timeout() {
+		let received = Arc::new(Mutex::new(Vec::new()));
+		let devtools = DevTools::connect(&server(received.clone())).unwrap();
+
+		if let MaybeTlsStream::Plain(stream) = devtools.socket.lock().unwrap().websocket.get_ref() {
+			assert_eq!(stream.read_timeout().unwrap(), Some(DevTools::CALL_TIMEOUT));
+			stream
+				.set_read_timeout(Some(Duration::from_millis(100)))
+				.unwrap();
+		}
+
+		assert_eq!(
+			devtools
+				.call(None, "Slow.method", json!({}))
+				.unwrap_err()
+				.to_string(),
+			"DevTools Slow.method timed out"
+		);
+		devtools.open("http://a/").unwrap();
+	}
+}
@@ -0,0 +1,159 @@
+/*
+ * Copyright 2025  Simon Arlott
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use std::process::Command;
+
+use anyhow::{Error, anyhow};
+use log::trace;
+use serde::Deserialize;
+
+/// How to turn the display on and off
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(tag = "power", rename_all = "lowercase", deny_unknown_fields)]
+pub enum DisplayPower {
+	/// Raspberry Pi firmware (legacy)
+	#[default]
+	Tvservice,
+	/// X11 display power management
+	Dpms,
+	/// Raspberry Pi firmware
+	Vcgencmd {
+		#[serde(default)]
+		display: Option<u8>,
+	},
+	/// DDC/CI over the I2C bus of the monitor cable
+	Ddc {
+		#[serde(default)]
+		bus: Option<u8>,
+	},
+	/// Shell commands
+	Command { sleep: String, resume: String },
+}
+
+impl DisplayPower {
+	/* DDC/CI VCP feature D6 (power mode) */
+	const DDC_POWER_MODE: &str = "d6";
+	const DDC_POWER_ON: &str = "01";
+	const DDC_POWER_OFF: &str = "04";
+
+	pub fn sleep(&self) -> Result<(), Error> {
+		match self {
+			Self::Tvservice => run(Command::new("tvservice").arg("-o")),
+			Self::Dpms => run(Command::new("xset").args(["dpms", "force", "off"])),
+			Self::Vcgencmd { display } => run(&mut Self::vcgencmd(false, *display)),
+			Self::Ddc { bus } => run(&mut Self::ddcutil(Self::DDC_POWER_OFF, *bus)),
+			Self::Command { sleep, .. } => run(Command::new("sh").arg("-c").arg(sleep)),
+		}
+	}
+
+	pub fn resume(&self) -> Result<(), Error> {
+		match self {
+			Self::Tvservice => {
+				run(Command::new("tvservice").arg("-p"))?;
+				/* The screen stays blank until it's redrawn */
+				run(&mut Command::new("xrefresh"))
+			}
+			Self::Dpms => {
+				run(Command::new("xset").args(["dpms", "force", "on"]))?;
+				run(Command::new("xset").args(["s", "reset"]))
+			}
+			Self::Vcgencmd { display } => run(&mut Self::vcgencmd(true, *display)),
+			Self::Ddc { bus } => run(&mut Self::ddcutil(Self::DDC_POWER_ON, *bus)),
+			Self::Command { resume, .. } => run(Command::new("sh").arg("-c").arg(resume)),
+		}
+	}
+
+	fn vcgencmd(on: bool, display: Option<u8>) -> Command {
+		let mut command = Command::new("vcgencmd");
+
+		command.arg("display_power").arg(if on { "1" } else { "0" });
+		if let Some(display) = display {
+			command.arg(display.to_string());
+		}
+		command
+	}
+
+	fn ddcutil(value: &str, bus: Option<u8>) -> Command {
+		let mut command = Command::new("ddcutil");
+
+		command.args(["setvcp", Self::DDC_POWER_MODE, value]);
+		if let Some(bus) = bus {
+			command.arg("--bus").arg(bus.to_string());
+		}
+		command
+	}
+}
+
+/// Run a command and wait for it to finish successfully
+fn run(command: &mut Command) -> Result<(), Error> {
+	trace!("Run: {command:?}");
+
+	let output = command.output()?;
+
+	if output.status.success() {
+		Ok(())
+	} else {
+		Err(anyhow!(
+			"{:?} failed ({}): {}",
+			command.get_program(),
+			output.status,
+			String::from_utf8_lossy(&output.stderr).trim()
+		))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::DisplayPower;
+
+	#[test]
+	fn shell_command() {
+		let power = DisplayPower::Command {
+			sleep: "true".to_owned(),
+			resume: "echo no display >&2; exit 1".to_owned(),
+		};
+
+		assert!(power.sleep().is_ok());
+		assert_eq!(
+			power.resume().unwrap_err().to_string(),
+			"\"sh\" failed (exit status: 1): no display"
+		);
+	}
+
+	#[test]
+	fn command_arguments() {
+		let args = |command: std::process::Command| {
+			command
+				.get_args()
+				.map(|arg| arg.to_str().unwrap().to_owned())
+				.collect::<Vec<_>>()
+		};
+
+		assert_eq!(
+			args(DisplayPower::vcgencmd(false, Some(2))),
+			["display_power", "0", "2"]
+		);
+		assert_eq!(
+			args(DisplayPower::vcgencmd(true, None)),
+			["display_power", "1"]
+		);
+		assert_eq!(
+			args(DisplayPower::ddcutil(DisplayPower::DDC_POWER_OFF, Some(1))),
+			["setvcp", "d6", "04", "--bus", "1"]
+		);
+	}
+}
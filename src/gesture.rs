@@ -0,0 +1,304 @@
+/*
+ * Copyright 2025  Simon Arlott
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use std::time::Instant;
+
+use evdev::{EventType, InputEvent};
+use indexmap::IndexMap;
+
+use crate::mapping::{Action, Mapping};
+
+/// Detects long presses, double presses and chords of keys
+///
+/// Keys without any of these gestures perform their action immediately.
+/// Otherwise the action is delayed until the key is released (or until it's
+/// too late to press it again) to check that it isn't part of a gesture.
+#[derive(Debug, Default)]
+pub struct Gestures {
+	/// Keys with gestures that are held down
+	held: IndexMap<u16, Held>,
+	/// Key that has been released, waiting to see if it's pressed again
+	released: Option<(u16, Instant)>,
+}
+
+#[derive(Debug)]
+struct Held {
+	since: Instant,
+	/// A gesture has been performed (nothing happens when it's released)
+	done: bool,
+}
+
+impl Gestures {
+	pub fn event(&mut self, mapping: &Mapping, event: &InputEvent, now: Instant) -> Vec<Action> {
+		if event.event_type() != EventType::KEY {
+			return Vec::new();
+		}
+
+		match event.value() {
+			1 => self.press(mapping, event.code(), now),
+			0 => self.release(mapping, event.code(), now),
+			_ => Vec::new(), /* Repeat */
+		}
+	}
+
+	fn press(&mut self, mapping: &Mapping, code: u16, now: Instant) -> Vec<Action> {
+		let mut actions = Vec::new();
+
+		if let Some((released, _)) = self.released
+			&& released != code
+		{
+			/* A different key was pressed instead of a double press */
+			self.released = None;
+			actions.extend(mapping.key(released).cloned());
+		}
+
+		let chord = self
+			.held
+			.iter()
+			.filter(|(_, held)| !held.done)
+			.find_map(|(other, _)| mapping.chord(*other, code).map(|action| (*other, action)));
+
+		if let Some((other, action)) = chord {
+			self.held.get_mut(&other).unwrap().done = true;
+			self.held.insert(code, Held::done(now));
+			actions.push(action.clone());
+		} else if self.released.take().is_some() {
+			self.held.insert(code, Held::done(now));
+			actions.extend(mapping.double(code).cloned());
+		} else if mapping.gestures(code) {
+			self.held.insert(
+				code,
+				Held {
+					since: now,
+					done: false,
+				},
+			);
+		} else {
+			actions.extend(mapping.key(code).cloned());
+		}
+
+		actions
+	}
+
+	fn release(&mut self, mapping: &Mapping, code: u16, now: Instant) -> Vec<Action> {
+		let Some(held) = self.held.shift_remove(&code) else {
+			return Vec::new();
+		};
+
+		if held.done {
+			Vec::new()
+		} else if let Some(action) = mapping.long(code)
+			&& now >= held.since + mapping.long_press
+		{
+			vec![action.clone()]
+		} else if mapping.double(code).is_some() {
+			self.released = Some((code, now));
+			Vec::new()
+		} else {
+			mapping.key(code).cloned().into_iter().collect()
+		}
+	}
+
+	/// Perform the gestures that have timed out
+	pub fn timeout(&mut self, mapping: &Mapping, now: Instant) -> Vec<Action> {
+		let mut actions = Vec::new();
+
+		for (code, held) in self.held.iter_mut() {
+			if !held.done
+				&& let Some(action) = mapping.long(*code)
+				&& now >= held.since + mapping.long_press
+			{
+				held.done = true;
+				actions.push(action.clone());
+			}
+		}
+
+		if let Some((code, released)) = self.released
+			&& now >= released + mapping.double_press
+		{
+			self.released = None;
+			actions.extend(mapping.key(code).cloned());
+		}
+
+		actions
+	}
+
+	/// When the next gesture will time out
+	pub fn deadline(&self, mapping: &Mapping) -> Option<Instant> {
+		self.held
+			.iter()
+			.filter(|(code, held)| !held.done && mapping.long(**code).is_some())
+			.map(|(_, held)| held.since + mapping.long_press)
+			.chain(
+				self.released
+					.map(|(_, released)| released + mapping.double_press),
+			)
+			.min()
+	}
+}
+
+impl Held {
+	fn done(now: Instant) -> Self {
+		Self {
+			since: now,
+			done: true,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::{Duration, Instant};
+
+	use evdev::{EventType, InputEvent};
+	use indexmap::IndexMap;
+
+	use crate::mapping::{Action, Mapping};
+
+	use super::Gestures;
+
+	const A: u16 = 288;
+	const B: u16 = 289;
+	const C: u16 = 290;
+	const D: u16 = 291;
+	const START: u16 = 297;
+
+	fn mapping() -> Mapping {
+		Mapping {
+			keys: IndexMap::from([
+				(A, Action::Goto("a".to_owned())),
+				(B, Action::Goto("b".to_owned())),
+				(C, Action::Goto("c".to_owned())),
+				(D, Action::Goto("d".to_owned())),
+				(START, Action::Pause),
+			]),
+			long: IndexMap::from([(START, Action::Sleep)]),
+			double: IndexMap::from([(B, Action::Reload)]),
+			chords: IndexMap::from([((A, C), Action::Next)]),
+			..Mapping::default()
+		}
+	}
+
+	/// Key events (code, value) at times in milliseconds
+	fn run(events: &[(u64, u16, i32)]) -> Vec<(u64, Action)> {
+		let mapping = mapping();
+		let mut gestures = Gestures::default();
+		let start = Instant::now();
+		let mut actions = Vec::new();
+		let ms = |ms| start + Duration::from_millis(ms);
+
+		for &(time, code, value) in events {
+			while let Some(deadline) = gestures.deadline(&mapping)
+				&& deadline <= ms(time)
+			{
+				let time = (deadline - start).as_millis() as u64;
+
+				actions.extend(
+					gestures
+						.timeout(&mapping, deadline)
+						.into_iter()
+						.map(|action| (time, action)),
+				);
+			}
+
+			actions.extend(
+				gestures
+					.event(
+						&mapping,
+						&InputEvent::new(EventType::KEY.0, code, value),
+						ms(time),
+					)
+					.into_iter()
+					.map(|action| (time, action)),
+			);
+		}
+
+		actions
+	}
+
+	fn goto(name: &str) -> Action {
+		Action::Goto(name.to_owned())
+	}
+
+	#[test]
+	fn plain_press() {
+		assert_eq!(
+			run(&[(0, D, 1), (10, D, 2), (50, D, 0), (60, 304, 1)]),
+			[(0, goto("d"))]
+		);
+	}
+
+	#[test]
+	fn long_press() {
+		assert_eq!(
+			run(&[(0, START, 1), (500, START, 0)]),
+			[(500, Action::Pause)]
+		);
+		assert_eq!(
+			run(&[(0, START, 1), (900, START, 2), (2000, START, 0)]),
+			[(800, Action::Sleep)]
+		);
+	}
+
+	#[test]
+	fn double_press() {
+		assert_eq!(
+			run(&[
+				(0, B, 1),
+				(50, B, 0),
+				(200, B, 1),
+				(250, B, 0),
+				(1000, A, 1)
+			]),
+			[(200, Action::Reload)]
+		);
+		assert_eq!(
+			run(&[
+				(0, B, 1),
+				(50, B, 0),
+				(1000, B, 1),
+				(1050, B, 0),
+				(2000, A, 1)
+			]),
+			[(350, goto("b")), (1350, goto("b"))]
+		);
+
+		/* Another key is pressed before the double press times out */
+		assert_eq!(
+			run(&[(0, B, 1), (50, B, 0), (100, START, 1), (200, START, 0)]),
+			[(100, goto("b")), (200, Action::Pause)]
+		);
+	}
+
+	#[test]
+	fn chord() {
+		assert_eq!(
+			run(&[(0, A, 1), (30, C, 1), (100, A, 0), (110, C, 0)]),
+			[(30, Action::Next)]
+		);
+		assert_eq!(
+			run(&[(0, C, 1), (30, A, 1), (100, C, 0), (110, A, 0)]),
+			[(30, Action::Next)]
+		);
+
+		/* Pressed separately */
+		assert_eq!(
+			run(&[(0, A, 1), (100, A, 0), (110, C, 1), (120, C, 0)]),
+			[(100, goto("a")), (120, goto("c"))]
+		);
+	}
+}
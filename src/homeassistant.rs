@@ -0,0 +1,297 @@
+/*
+ * Copyright 2025  Simon Arlott
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use std::{
+	collections::HashSet,
+	sync::{Arc, Mutex, mpsc},
+	thread,
+};
+
+use indexmap::IndexMap;
+use log::{debug, info};
+use rumqttc::QoS;
+use serde_json::{Value, json};
+
+use crate::config::{Config, MqttTopics};
+use crate::mqtt::Mqtt;
+
+/// Home Assistant MQTT discovery
+///
+/// Publishes the entities of the status screen device (the current tab, the
+/// autoscroll pause and display power switches and a button for each timer)
+/// using the existing state and command topics.
+#[derive(Debug)]
+pub struct HomeAssistant {
+	config: Arc<Config>,
+	prefix: String,
+	mqtt: Arc<Mqtt>,
+	/// Discovery topics that have been published
+	published: Mutex<HashSet<String>>,
+}
+
+impl HomeAssistant {
+	pub fn start(config: Arc<Config>, mqtt: Arc<Mqtt>) {
+		let Some(prefix) = config.homeassistant_discovery_prefix() else {
+			return;
+		};
+		let births = mqtt.subscribe(&format!("{prefix}/status"), QoS::AtLeastOnce);
+		let connects = mqtt.subscribe_connect();
+		let config_changes = config.subscribe();
+		let self_copy = Arc::new(Self {
+			config,
+			prefix,
+			mqtt,
+			published: Mutex::new(HashSet::new()),
+		});
+		let (tx, rx) = mpsc::channel();
+
+		thread::spawn(move || {
+			while rx.recv().is_ok() {
+				while rx.try_recv().is_ok() {}
+				self_copy.publish();
+			}
+		});
+
+		let tx_copy = tx.clone();
+
+		thread::spawn(move || {
+			for _ in connects {
+				let _ = tx_copy.send(());
+			}
+		});
+
+		let tx_copy = tx.clone();
+
+		thread::spawn(move || {
+			for msg in births {
+				if &msg.payload[..] == b"online" {
+					info!("Home Assistant started");
+					let _ = tx_copy.send(());
+				}
+			}
+		});
+
+		thread::spawn(move || {
+			for sections in config_changes {
+				if sections.contains(&"urls") || sections.contains(&"timers") {
+					let _ = tx.send(());
+				}
+			}
+		});
+	}
+
+	fn publish(&self) {
+		let entities = Self::entities(
+			&self.prefix,
+			self.mqtt.topics(),
+			&self
+				.config
+				.browser_urls()
+				.keys()
+				.cloned()
+				.collect::<Vec<_>>(),
+			&self.config.timers(),
+		);
+		let mut published = self.published.lock().unwrap();
+		let topics: HashSet<String> = entities.iter().map(|(topic, _)| topic.clone()).collect();
+
+		debug!("Publish Home Assistant discovery");
+
+		for (topic, payload) in entities {
+			self.mqtt
+				.publish(topic, QoS::AtLeastOnce, true, payload.to_string());
+		}
+
+		/* Remove entities that no longer exist */
+		for topic in published.difference(&topics) {
+			self.mqtt.publish(topic, QoS::AtLeastOnce, true, "");
+		}
+
+		*published = topics;
+	}
+
+	/// Discovery topics and configs for all of the entities
+	fn entities(
+		prefix: &str,
+		topics: &MqttTopics,
+		tabs: &[String],
+		timers: &IndexMap<u16, String>,
+	) -> Vec<(String, Value)> {
+		let node_id = Self::node_id(topics);
+		let state_topic = |name: &str| topics.state(name);
+		let command_topic = |name: &str| topics.command(name);
+		let mut entities = vec![
+			(
+				"select",
+				"tab".to_owned(),
+				json!({
+					"name": "Screen",
+					"icon": "mdi:monitor-dashboard",
+					"state_topic": state_topic("screen"),
+					"command_topic": command_topic("goto"),
+					"options": tabs,
+				}),
+			),
+			(
+				"sensor",
+				"screen".to_owned(),
+				json!({
+					"name": "Current screen",
+					"icon": "mdi:monitor-dashboard",
+					"state_topic": state_topic("screen"),
+				}),
+			),
+			(
+				"switch",
+				"pause".to_owned(),
+				json!({
+					"name": "Autoscroll pause",
+					"icon": "mdi:pause",
+					"state_topic": state_topic("mode"),
+					"value_template": "{{ 'ON' if value == 'paused' else 'OFF' }}",
+					"command_topic": command_topic("paused"),
+				}),
+			),
+			(
+				"switch",
+				"display".to_owned(),
+				json!({
+					"name": "Display",
+					"icon": "mdi:monitor",
+					"state_topic": state_topic("display"),
+					"state_on": "on",
+					"state_off": "off",
+					"command_topic": command_topic("display"),
+				}),
+			),
+		];
+
+		for (id, name) in timers.iter() {
+			entities.push((
+				"button",
+				format!("timer_{id}"),
+				json!({
+					"name": format!("Reset {name}"),
+					"icon": "mdi:timer-refresh",
+					"command_topic": command_topic("timer"),
+					"payload_press": name,
+				}),
+			));
+		}
+
+		entities
+			.into_iter()
+			.map(|(component, object_id, mut config)| {
+				config["unique_id"] = json!(format!("{node_id}_{object_id}"));
+				config["availability_topic"] = json!(topics.availability());
+				config["device"] = json!({
+					"identifiers": [node_id],
+					"name": "Status screen",
+					"manufacturer": "Edinburgh Hacklab",
+					"model": "hacklab-status-screen",
+				});
+
+				(
+					format!("{prefix}/{component}/{node_id}/{object_id}/config"),
+					config,
+				)
+			})
+			.collect()
+	}
+
+	/// Identify the device by its topic prefix so that multiple status screens
+	/// can be discovered
+	fn node_id(topics: &MqttTopics) -> String {
+		topics
+			.prefix
+			.chars()
+			.map(|c| {
+				if c.is_ascii_alphanumeric() {
+					c.to_ascii_lowercase()
+				} else {
+					'_'
+				}
+			})
+			.collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use indexmap::IndexMap;
+	use serde_json::json;
+
+	use crate::config::MqttTopics;
+
+	use super::HomeAssistant;
+
+	#[test]
+	fn entities() {
+		let entities = HomeAssistant::entities(
+			"homeassistant",
+			&MqttTopics::default(),
+			&["buses".to_owned(), "map".to_owned()],
+			&IndexMap::from([(0, "Nix Mentioned".to_owned()), (5, "bark".to_owned())]),
+		);
+
+		assert_eq!(
+			entities
+				.iter()
+				.map(|(topic, _)| topic.as_str())
+				.collect::<Vec<_>>(),
+			[
+				"homeassistant/select/status_screen/tab/config",
+				"homeassistant/sensor/status_screen/screen/config",
+				"homeassistant/switch/status_screen/pause/config",
+				"homeassistant/switch/status_screen/display/config",
+				"homeassistant/button/status_screen/timer_0/config",
+				"homeassistant/button/status_screen/timer_5/config",
+			]
+		);
+
+		let (_, select) = &entities[0];
+		assert_eq!(select["options"], json!(["buses", "map"]));
+		assert_eq!(select["command_topic"], "status-screen/cmd/goto");
+		assert_eq!(select["unique_id"], "status_screen_tab");
+		assert_eq!(select["device"]["identifiers"], json!(["status_screen"]));
+
+		let (_, button) = &entities[4];
+		assert_eq!(button["name"], "Reset Nix Mentioned");
+		assert_eq!(button["command_topic"], "status-screen/cmd/timer");
+		assert_eq!(button["payload_press"], "Nix Mentioned");
+		assert_eq!(button["availability_topic"], "status-screen/availability");
+	}
+
+	#[test]
+	fn node_id() {
+		let topics = MqttTopics {
+			prefix: "hacklab/Status-Screen2".to_owned(),
+			..MqttTopics::default()
+		};
+		let entities = HomeAssistant::entities("homeassistant", &topics, &[], &IndexMap::new());
+
+		assert_eq!(
+			entities[0].0,
+			"homeassistant/select/hacklab_status_screen2/tab/config"
+		);
+		assert_eq!(entities[0].1["unique_id"], "hacklab_status_screen2_tab");
+		assert_eq!(
+			entities[0].1["device"]["identifiers"],
+			json!(["hacklab_status_screen2"])
+		);
+	}
+}
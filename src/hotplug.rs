@@ -0,0 +1,215 @@
+/*
+ * Copyright 2025  Simon Arlott
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use std::{
+	path::PathBuf,
+	sync::mpsc::{self, Receiver, Sender},
+	thread,
+	time::Duration,
+};
+
+use anyhow::Error;
+use glob::Pattern;
+use log::{debug, error};
+use mio::{Events, Interest, Poll, Token};
+use serde::Serialize;
+use udev::{Enumerator, EventType, MonitorBuilder};
+
+use crate::config::DeviceMatch;
+
+/// Input event device
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DeviceInfo {
+	/// Device node (/dev/input/event*)
+	pub path: PathBuf,
+	/// Symlinks to the device node (/dev/input/by-id/*)
+	#[serde(skip)]
+	pub links: Vec<PathBuf>,
+	pub name: Option<String>,
+	pub vendor: Option<u16>,
+	pub product: Option<u16>,
+	pub serial: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+	Added(DeviceInfo),
+	Removed(DeviceInfo),
+}
+
+/// Input devices being added and removed (using udev)
+#[derive(Debug)]
+pub struct Hotplug;
+
+impl Hotplug {
+	const RETRY: Duration = Duration::from_secs(5);
+
+	/// Receive events for all of the existing devices and then every time a
+	/// device is added or removed
+	pub fn start() -> Receiver<Event> {
+		let (tx, rx) = mpsc::channel();
+
+		thread::spawn(move || {
+			loop {
+				if let Err(err) = Self::run(&tx) {
+					error!("Unable to monitor input devices: {err}");
+				}
+				thread::sleep(Self::RETRY);
+			}
+		});
+
+		rx
+	}
+
+	fn run(tx: &Sender<Event>) -> Result<(), Error> {
+		/* Start monitoring before looking for existing devices so that none are missed */
+		let mut socket = MonitorBuilder::new()?.match_subsystem("input")?.listen()?;
+		let mut poll = Poll::new()?;
+		let mut events = Events::with_capacity(1);
+		let mut enumerator = Enumerator::new()?;
+
+		poll.registry()
+			.register(&mut socket, Token(0), Interest::READABLE)?;
+		enumerator.match_subsystem("input")?;
+		enumerator.match_is_initialized()?;
+
+		for device in enumerator.scan_devices()? {
+			if let Some(info) = Self::info(&device) {
+				let _ = tx.send(Event::Added(info));
+			}
+		}
+
+		loop {
+			poll.poll(&mut events, None)?;
+
+			for event in socket.iter() {
+				let Some(info) = Self::info(&event) else {
+					continue;
+				};
+
+				debug!("Input device {}: {info:?}", event.event_type());
+				let _ = tx.send(match event.event_type() {
+					EventType::Add => Event::Added(info),
+					EventType::Remove => Event::Removed(info),
+					_ => continue,
+				});
+			}
+		}
+	}
+
+	fn info(device: &udev::Device) -> Option<DeviceInfo> {
+		if !device.sysname().to_string_lossy().starts_with("event") {
+			return None;
+		}
+
+		let property = |key| {
+			device
+				.property_value(key)
+				.map(|value| value.to_string_lossy().into_owned())
+		};
+		let id = |key| property(key).and_then(|value| u16::from_str_radix(&value, 16).ok());
+
+		Some(DeviceInfo {
+			path: device.devnode()?.to_path_buf(),
+			links: property("DEVLINKS")
+				.map(|links| links.split_whitespace().map(PathBuf::from).collect())
+				.unwrap_or_default(),
+			name: device.parent().and_then(|parent| {
+				parent
+					.attribute_value("name")
+					.map(|name| name.to_string_lossy().into_owned())
+			}),
+			vendor: id("ID_VENDOR_ID"),
+			product: id("ID_MODEL_ID"),
+			serial: property("ID_SERIAL_SHORT"),
+		})
+	}
+}
+
+impl DeviceMatch {
+	pub fn matches(&self, device: &DeviceInfo) -> bool {
+		self.path
+			.as_ref()
+			.is_none_or(|path| device.path == *path || device.links.contains(path))
+			&& self
+				.vendor
+				.is_none_or(|vendor| device.vendor == Some(vendor))
+			&& self
+				.product
+				.is_none_or(|product| device.product == Some(product))
+			&& self
+				.serial
+				.as_ref()
+				.is_none_or(|serial| device.serial.as_ref() == Some(serial))
+			&& self.name.as_ref().is_none_or(|name| {
+				device.name.as_ref().is_some_and(|device_name| {
+					Pattern::new(name).is_ok_and(|pattern| pattern.matches(device_name))
+				})
+			})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::path::PathBuf;
+
+	use crate::config::DeviceMatch;
+
+	use super::DeviceInfo;
+
+	#[test]
+	fn device_match() {
+		let device = DeviceInfo {
+			path: PathBuf::from("/dev/input/event3"),
+			links: vec![PathBuf::from(
+				"/dev/input/by-id/usb-0810_usb_gamepad-event-joystick",
+			)],
+			name: Some("usb gamepad           ".to_owned()),
+			vendor: Some(0x0810),
+			product: Some(0xe501),
+			serial: None,
+		};
+		let matches = |device_match: DeviceMatch| device_match.matches(&device);
+		let path = |path: &str| DeviceMatch {
+			path: Some(PathBuf::from(path)),
+			..DeviceMatch::default()
+		};
+		let name = |name: &str| DeviceMatch {
+			name: Some(name.to_owned()),
+			..DeviceMatch::default()
+		};
+		let id = |vendor, product| DeviceMatch {
+			vendor: Some(vendor),
+			product: Some(product),
+			..DeviceMatch::default()
+		};
+
+		assert!(matches(path("/dev/input/event3")));
+		assert!(matches(path(
+			"/dev/input/by-id/usb-0810_usb_gamepad-event-joystick"
+		)));
+		assert!(!matches(path("/dev/input/event4")));
+		assert!(matches(id(0x0810, 0xe501)));
+		assert!(!matches(id(0x0810, 0xe502)));
+		assert!(matches(name("usb gamepad*")));
+		assert!(!matches(name("*keyboard*")));
+		assert!(!matches(DeviceMatch {
+			serial: Some("E66368254F206F35".to_owned()),
+			..id(0x0810, 0xe501)
+		}));
+	}
+}
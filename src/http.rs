@@ -0,0 +1,261 @@
+/*
+ * Copyright 2025  Simon Arlott
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use std::{sync::Arc, thread};
+
+use log::{debug, error, info};
+use serde_json::{Value, json};
+use tiny_http::{Header, Method, Response, Server};
+
+use crate::config::Config;
+use crate::output::{Browser, TimeSinceLast};
+
+/// HTTP control API
+///
+/// `GET /status` returns the current state, `POST` to `/next`, `/previous`,
+/// `/reload`, `/pause`, `/hold`, `/resume`, `/goto/<tab>` or
+/// `/timers/<name>/reset` to control the screen.
+#[derive(Debug)]
+pub struct Http {
+	config: Arc<Config>,
+	browser: Arc<Browser>,
+	time_since_last: Arc<TimeSinceLast>,
+}
+
+impl Http {
+	pub fn new(
+		config: Arc<Config>,
+		browser: Arc<Browser>,
+		time_since_last: Arc<TimeSinceLast>,
+	) -> Arc<Self> {
+		Arc::new(Self {
+			config,
+			browser,
+			time_since_last,
+		})
+	}
+
+	pub fn start(self: &Arc<Self>, listen: &str) {
+		match Server::http(listen) {
+			Ok(server) => {
+				let self_copy = self.clone();
+
+				info!("HTTP server listening on {listen}");
+				thread::spawn(move || self_copy.run(server));
+			}
+			Err(err) => error!("Unable to start HTTP server on {listen}: {err}"),
+		}
+	}
+
+	fn run(&self, server: Server) {
+		for request in server.incoming_requests() {
+			let (status, body) = self.handle(request.method(), request.url());
+
+			debug!(
+				"HTTP {} {} from {:?}: {status}",
+				request.method(),
+				request.url(),
+				request.remote_addr()
+			);
+
+			let response = Response::from_string(body.to_string())
+				.with_status_code(status)
+				.with_header(
+					Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap(),
+				);
+
+			if let Err(err) = request.respond(response) {
+				error!("Unable to send HTTP response: {err}");
+			}
+		}
+	}
+
+	fn handle(&self, method: &Method, url: &str) -> (u16, Value) {
+		let path = url.split_once("?").map_or(url, |(path, _)| path);
+		let segments: Vec<String> = path
+			.split("/")
+			.filter(|segment| !segment.is_empty())
+			.map(percent_decode)
+			.collect();
+		let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+
+		match (method, segments.as_slice()) {
+			(Method::Get, ["status"]) => {}
+			(Method::Post, ["next"]) => self.browser.goto_next_tab(),
+			(Method::Post, ["previous"]) => self.browser.goto_previous_tab(),
+			(Method::Post, ["reload"]) => self.browser.reload_tab(),
+			(Method::Post, ["pause"]) => self.browser.pause(),
+			(Method::Post, ["hold"]) => self.browser.hold_tab(),
+			(Method::Post, ["resume"]) => self.browser.resume(),
+			(Method::Post, ["goto", name]) => {
+				if !self.browser.goto_by_name(name, false) {
+					return (404, json!({ "error": format!("Tab {name} not found") }));
+				}
+			}
+			(Method::Post, ["timers", name, "reset"]) => {
+				if !self.config.timer_configured(name) {
+					return (404, json!({ "error": format!("Timer {name} not found") }));
+				}
+				self.time_since_last.reset(name);
+			}
+			(
+				_,
+				["status"]
+				| ["next"]
+				| ["previous"]
+				| ["reload"]
+				| ["pause"]
+				| ["hold"]
+				| ["resume"]
+				| ["goto", _]
+				| ["timers", _, "reset"],
+			) => return (405, json!({ "error": "Method not allowed" })),
+			_ => return (404, json!({ "error": "Not found" })),
+		}
+
+		(200, json!(self.browser.status()))
+	}
+}
+
+/// Decode %XX escapes in a url path segment
+fn percent_decode(segment: &str) -> String {
+	let mut bytes = Vec::new();
+	let mut input = segment.bytes();
+
+	while let Some(byte) = input.next() {
+		if byte == b'%' {
+			let hex = [input.next(), input.next()];
+
+			if let [Some(high), Some(low)] = hex
+				&& let Ok(value) = u8::from_str_radix(&String::from_utf8_lossy(&[high, low]), 16)
+			{
+				bytes.push(value);
+			} else {
+				bytes.push(byte);
+				bytes.extend(hex.into_iter().flatten());
+			}
+		} else {
+			bytes.push(byte);
+		}
+	}
+
+	String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+
+	use serde_json::json;
+	use tiny_http::Method;
+
+	use crate::backend::fake::Fake;
+	use crate::config::Config;
+	use crate::mqtt::Mqtt;
+	use crate::output::{Browser, TimeSinceLast};
+
+	use super::{Http, percent_decode};
+
+	fn http() -> (Arc<Http>, Fake) {
+		let config = Config::from_toml(
+			"[keyboards]\nmain = \"/dev/input/event0\"\n\
+			[urls]\nbuses = \"http://localhost/buses\"\nmap = \"http://localhost/map\"\n\
+			[timers]\n0 = \"Nix Mentioned\"\n",
+		);
+		let fake = Fake::default();
+		let browser = Browser::with_backend(false, config.clone(), Box::new(fake.clone()));
+
+		(
+			Http::new(
+				config.clone(),
+				browser,
+				TimeSinceLast::new(Mqtt::new(&config).unwrap()),
+			),
+			fake,
+		)
+	}
+
+	#[test]
+	fn status() {
+		let (http, _) = http();
+
+		assert_eq!(
+			http.handle(&Method::Get, "/status"),
+			(
+				200,
+				json!({
+					"tab": 1,
+					"name": "buses",
+					"url": "http://localhost/buses",
+					"mode": "autoscroll",
+					"next_autoscroll": null,
+					"display_sleeping": false,
+					"input": null,
+				})
+			)
+		);
+	}
+
+	#[test]
+	fn commands() {
+		let (http, fake) = http();
+
+		let (status, body) = http.handle(&Method::Post, "/next");
+		assert_eq!(status, 200);
+		assert_eq!(body["name"], "map");
+		assert_eq!(body["mode"], "held");
+
+		let (_, body) = http.handle(&Method::Post, "/pause");
+		assert_eq!(body["mode"], "paused");
+
+		let (_, body) = http.handle(&Method::Post, "/resume");
+		assert_eq!(body["mode"], "autoscroll");
+
+		let (_, body) = http.handle(&Method::Post, "/goto/buses?from=laptop");
+		assert_eq!(body["tab"], 1);
+
+		http.handle(&Method::Post, "/reload");
+		assert_eq!(
+			fake.take(),
+			["press 1 Ctrl+2", "press 2 Ctrl+1", "press 1 Ctrl+r"]
+		);
+
+		let (status, _) = http.handle(&Method::Post, "/timers/Nix%20Mentioned/reset");
+		assert_eq!(status, 200);
+	}
+
+	#[test]
+	fn errors() {
+		let (http, fake) = http();
+
+		assert_eq!(http.handle(&Method::Post, "/goto/trains").0, 404);
+		assert_eq!(http.handle(&Method::Post, "/timers/Nix/reset").0, 404);
+		assert_eq!(http.handle(&Method::Get, "/next").0, 405);
+		assert_eq!(http.handle(&Method::Post, "/status").0, 405);
+		assert_eq!(http.handle(&Method::Get, "/").0, 404);
+		assert_eq!(http.handle(&Method::Post, "/goto").0, 404);
+		assert!(fake.take().is_empty());
+	}
+
+	#[test]
+	fn decode() {
+		assert_eq!(percent_decode("Nix%20Mentioned"), "Nix Mentioned");
+		assert_eq!(percent_decode("Is%20this%20loss%3F"), "Is this loss?");
+		assert_eq!(percent_decode("100%"), "100%");
+		assert_eq!(percent_decode("%zz"), "%zz");
+	}
+}
@@ -15,45 +15,54 @@
  * along with this program.  If not, see <http://www.gnu.org/licenses/>.
  */
 
-use core::time;
 use enum_dispatch::enum_dispatch;
-use rumqttc::{Event, Incoming, MqttOptions, QoS};
-use std::cmp::Ordering;
-use std::collections::VecDeque;
-use std::path::{Path, PathBuf};
+use rumqttc::QoS;
+use std::collections::HashMap;
 use std::process::Command;
-use std::sync::{Arc, Mutex};
-use std::time::Duration;
-use std::{fmt, thread};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+use std::{fmt, io, thread};
 
-use anyhow::Error;
-use evdev::{EventType, InputEvent};
-use log::{debug, error, info, trace, warn};
+use anyhow::{Context, Error};
+use chrono::Local;
+use evdev::uinput::VirtualDevice;
+use evdev::{AbsoluteAxisCode, AttributeSet, EventSummary, EventType, InputEvent, KeyCode};
+use log::{debug, error, info, warn};
+use serde_json::json;
 
 use crate::config::Config;
+use crate::gesture::Gestures;
+use crate::hotplug::{self, DeviceInfo, Hotplug};
+use crate::mapping::{Action, Direction, Zone};
+use crate::mqtt::Mqtt;
 use crate::output::{Browser, TimeSinceLast};
+use crate::sequence::{Progress, Sequences};
+use crate::sleep::{self, SleepState, Timing};
 
 #[derive(Debug)]
 struct Device {
 	name: String,
-	path: PathBuf,
+	config: Arc<Config>,
+	browser: Arc<Browser>,
+	idle: Arc<Idle>,
+	actions: Arc<Actions>,
 	handler: Handlers,
+	/// Input device that is currently being read
+	attached: Mutex<Option<Arc<DeviceInfo>>>,
+	/// Zones of the absolute axes
+	axes: Mutex<HashMap<u16, Zone>>,
+	gestures: Mutex<Gestures>,
+	/// Gesture state has changed (the next timeout may be different)
+	gestures_changed: Condvar,
+	sequences: Mutex<Sequences>,
+	/// Virtual keyboard for passthrough keys (and the keys it has)
+	uinput: Mutex<Option<(Vec<u16>, VirtualDevice)>>,
 }
 
 #[derive(Debug)]
 pub struct Input {
-	main: Arc<Device>,
-	tabs: Option<Arc<Device>>,
-	timers: Option<Arc<Device>>,
-	_idle: Arc<Idle>,
-}
-
-#[derive(Debug)]
-enum Direction {
-	Up,
-	Down,
-	Left,
-	Right,
+	mqtt: Arc<Mqtt>,
+	devices: Vec<Arc<Device>>,
 }
 
 #[enum_dispatch]
@@ -73,7 +82,6 @@ enum Handlers {
 #[derive(Debug)]
 struct Navigation {
 	browser: Arc<Browser>,
-	konami: Konami,
 }
 
 #[derive(Debug)]
@@ -90,23 +98,32 @@ struct Timers {
 	time_since_last: Arc<TimeSinceLast>,
 }
 
+/// Performs the actions that inputs are bound to
 #[derive(Debug)]
-struct Konami {
-	config: Arc<Config>,
-	history: Mutex<VecDeque<char>>,
+struct Actions {
+	browser: Arc<Browser>,
+	mqtt: Arc<Mqtt>,
 	run: Arc<Mutex<Arc<Browser>>>,
+	time_since_last: Arc<TimeSinceLast>,
 }
 
+/// Commands received over MQTT
 #[derive(Debug)]
-struct Clip {
-    //vid_dir: String,
-    //playing: bool,
+pub struct Remote {
+	browser: Arc<Browser>,
+	config: Arc<Config>,
+	run: Arc<Mutex<Arc<Browser>>>,
+	time_since_last: Arc<TimeSinceLast>,
+	idle: Arc<Idle>,
 }
 
-#[derive(derive_more::Debug)]
+/// Puts the display to sleep when the space is empty
+#[derive(Debug)]
 struct Idle {
-	#[debug("{:?}", _client.is_some())]
-	_client: Option<rumqttc::Client>,
+	browser: Arc<Browser>,
+	config: Arc<Config>,
+	state: Mutex<SleepState>,
+	changed: Condvar,
 }
 
 fn execute(run: Arc<Mutex<Arc<Browser>>>, command: &str) {
@@ -129,96 +146,325 @@ fn execute(run: Arc<Mutex<Arc<Browser>>>, command: &str) {
 	});
 }
 
+/// Print the inputs of all devices as they're used (to find the codes for
+/// the [devices] config)
+pub fn learn() {
+	for event in Hotplug::start() {
+		match event {
+			hotplug::Event::Added(device) => {
+				println!(
+					"{}: added {:?}",
+					device.path.display(),
+					device.name.as_deref().unwrap_or_default()
+				);
+				thread::spawn(move || learn_device(&device));
+			}
+			hotplug::Event::Removed(device) => println!("{}: removed", device.path.display()),
+		}
+	}
+}
+
+fn learn_device(device: &DeviceInfo) {
+	let path = device.path.display();
+	let mut evdev = match evdev::Device::open(&device.path) {
+		Ok(evdev) => evdev,
+		Err(err) => {
+			println!("{path}: {err}");
+			return;
+		}
+	};
+
+	while let Ok(events) = evdev.fetch_events() {
+		for event in events {
+			match event.destructure() {
+				EventSummary::Key(_, code, 1) => {
+					println!("{path}: keys.{} ({code:?})", code.code())
+				}
+				EventSummary::AbsoluteAxis(_, code, value)
+					if (AbsoluteAxisCode::ABS_HAT0X.0..=AbsoluteAxisCode::ABS_HAT3Y.0)
+						.contains(&code.0) =>
+				{
+					println!(
+						"{path}: hats.{} ({code:?}) = {value}",
+						(code.0 - AbsoluteAxisCode::ABS_HAT0X.0) / 2
+					)
+				}
+				EventSummary::AbsoluteAxis(_, code, value) => {
+					println!("{path}: axes.{} ({code:?}) = {value}", code.0)
+				}
+				_ => {}
+			}
+		}
+	}
+}
 
 impl Input {
 	pub fn new(
 		config: Arc<Config>,
 		browser: Arc<Browser>,
+		mqtt: Arc<Mqtt>,
 		time_since_last: Arc<TimeSinceLast>,
-	) -> Result<Self, Error> {
+	) -> Self {
 		let run = Arc::new(Mutex::new(browser.clone()));
-
-		Ok(Self {
-			_idle: Idle::new(&config, browser.clone(), run.clone()),
-			main: Device::new(
-				"main",
-				PathBuf::from(config.keyboard_device("main")?),
-				Handlers::from(Navigation::new(
-					browser.clone(),
-					config.clone(),
-					run.clone(),
-				)),
+		let idle = Idle::new(browser.clone(), config.clone());
+		let actions = Arc::new(Actions::new(
+			browser.clone(),
+			mqtt.clone(),
+			run.clone(),
+			time_since_last.clone(),
+		));
+
+		idle.start(&mqtt);
+		Arc::new(Remote::new(
+			browser.clone(),
+			config.clone(),
+			run.clone(),
+			time_since_last.clone(),
+			idle.clone(),
+		))
+		.start(&mqtt);
+
+		let mut devices = vec![
+			(
+				"main".to_owned(),
+				Handlers::from(Navigation::new(browser.clone())),
 			),
-			tabs: Device::new_optional(
-				"tabs",
-				config.keyboard_device("tabs").map(PathBuf::from),
+			(
+				"tabs".to_owned(),
 				Handlers::from(Tabs::new(browser.clone(), config.clone(), run)),
 			),
-			timers: Device::new_optional(
-				"timers",
-				config.keyboard_device("timers").map(PathBuf::from),
-				Handlers::from(Timers::new(browser, config, time_since_last.clone())),
+			(
+				"timers".to_owned(),
+				Handlers::from(Timers::new(
+					browser.clone(),
+					config.clone(),
+					time_since_last,
+				)),
 			),
-		})
+		];
+
+		for (name, _) in devices.iter() {
+			if config.keyboard(name).is_none() {
+				warn!("Keyboard {name} not configured");
+			}
+		}
+
+		/* Other keyboards use the buttons and D-pad the same way as main */
+		for name in config.keyboard_names() {
+			if !devices.iter().any(|(device, _)| *device == name) {
+				devices.push((name, Handlers::from(Navigation::new(browser.clone()))));
+			}
+		}
+
+		let devices = devices
+			.into_iter()
+			.map(|(name, handler)| {
+				Device::new(
+					&name,
+					config.clone(),
+					browser.clone(),
+					idle.clone(),
+					actions.clone(),
+					handler,
+				)
+			})
+			.collect();
+
+		Self { mqtt, devices }
 	}
 
 	pub fn start(&self) {
-		self.main.start();
-		if let Some(tabs) = &self.tabs {
-			tabs.start();
-		}
-		if let Some(timers) = &self.timers {
-			timers.start();
+		let events = Hotplug::start();
+		let mqtt = self.mqtt.clone();
+		let devices = self.devices.clone();
+
+		for device in self.devices.iter() {
+			device.start();
 		}
+
+		thread::spawn(move || {
+			for event in events {
+				for device in devices.iter() {
+					if let Some(info) = device.hotplug(&event) {
+						mqtt.publish(
+							mqtt.topics().state("device"),
+							QoS::AtLeastOnce,
+							false,
+							json!({
+								"keyboard": device.name,
+								"event": match event {
+									hotplug::Event::Added(_) => "added",
+									hotplug::Event::Removed(_) => "removed",
+								},
+								"device": *info,
+							})
+							.to_string(),
+						);
+					}
+				}
+			}
+		});
 	}
 }
 
 impl Device {
-	pub fn new<P: AsRef<Path>>(name: &str, path: P, handler: Handlers) -> Arc<Self> {
+	/// Time between attempts to open a device that is present
+	const RETRY: Duration = Duration::from_secs(1);
+
+	pub fn new(
+		name: &str,
+		config: Arc<Config>,
+		browser: Arc<Browser>,
+		idle: Arc<Idle>,
+		actions: Arc<Actions>,
+		handler: Handlers,
+	) -> Arc<Self> {
 		Arc::new(Self {
 			name: name.to_owned(),
-			path: path.as_ref().to_path_buf(),
+			config,
+			browser,
+			idle,
+			actions,
 			handler,
+			attached: Mutex::new(None),
+			axes: Mutex::new(HashMap::new()),
+			gestures: Mutex::new(Gestures::default()),
+			gestures_changed: Condvar::new(),
+			sequences: Mutex::new(Sequences::default()),
+			uinput: Mutex::new(None),
 		})
 	}
 
-	pub fn new_optional<P: AsRef<Path>>(
-		name: &str,
-		path: Result<P, Error>,
-		handler: Handlers,
-	) -> Option<Arc<Self>> {
-		match path {
-			Ok(path) => Some(Self::new(name, path, handler)),
-			Err(err) => {
-				warn!("Keyboard {name} not found: {err}");
-				None
+	pub fn start(self: &Arc<Self>) {
+		let self_copy = self.clone();
+
+		thread::spawn(move || self_copy.run_gestures());
+	}
+
+	/// Perform gestures that time out while keys are held down or after
+	/// they're released
+	fn run_gestures(&self) {
+		let mut gestures = self.gestures.lock().unwrap();
+
+		loop {
+			let mapping = self.config.device_mapping(&self.name);
+			let actions = gestures.timeout(&mapping, Instant::now());
+
+			if !actions.is_empty() {
+				drop(gestures);
+				for action in actions {
+					self.press(action);
+				}
+				gestures = self.gestures.lock().unwrap();
+				continue;
 			}
+
+			gestures = match gestures.deadline(&mapping) {
+				Some(deadline) => {
+					let timeout = deadline.saturating_duration_since(Instant::now());
+
+					self.gestures_changed
+						.wait_timeout(gestures, timeout)
+						.unwrap()
+						.0
+				}
+				None => self.gestures_changed.wait(gestures).unwrap(),
+			};
 		}
 	}
 
-	pub fn start(self: &Arc<Self>) {
-		let self_copy = self.clone();
+	/// Attach a matching device when it's added and detach it when it's
+	/// removed, returning the device if it was attached or detached
+	fn hotplug(self: &Arc<Self>, event: &hotplug::Event) -> Option<Arc<DeviceInfo>> {
+		let mut attached = self.attached.lock().unwrap();
+
+		match event {
+			hotplug::Event::Added(device) => {
+				if attached.is_some()
+					|| !self
+						.config
+						.keyboard(&self.name)
+						.is_some_and(|keyboard| keyboard.matches(device))
+				{
+					return None;
+				}
 
-		thread::spawn(move || self_copy.run());
+				let device = Arc::new(device.clone());
+				let self_copy = self.clone();
+				let device_copy = device.clone();
+
+				info!(
+					"[{}] Device added: {:?} ({})",
+					self.name,
+					device.path.display(),
+					device.name.as_deref().unwrap_or_default()
+				);
+				*attached = Some(device.clone());
+				thread::spawn(move || self_copy.run(&device_copy));
+				Some(device)
+			}
+			hotplug::Event::Removed(device) => {
+				if attached
+					.as_ref()
+					.is_none_or(|attached| attached.path != device.path)
+				{
+					return None;
+				}
+
+				info!(
+					"[{}] Device removed: {:?}",
+					self.name,
+					device.path.display()
+				);
+				*self.gestures.lock().unwrap() = Gestures::default();
+				self.axes.lock().unwrap().clear();
+				self.uinput.lock().unwrap().take();
+				attached.take()
+			}
+		}
 	}
 
-	fn run(&self) {
+	/// Read events until the device is removed
+	fn run(&self, device: &Arc<DeviceInfo>) {
 		loop {
-			match evdev::Device::open(&self.path) {
-				Ok(mut device) => {
-					info!("[{}] Opened device {:?}", self.name, self.path.display());
-					if let Err(err) = self.read_events(&mut device) {
-						error!("[{}] Error reading events: {err}", self.name);
-					}
+			let result = match evdev::Device::open(&device.path) {
+				Ok(mut evdev) => {
+					info!("[{}] Opened device {:?}", self.name, device.path.display());
+					self.grab(&mut evdev)
+						.and_then(|()| self.read_events(&mut evdev))
 				}
-				Err(err) => error!(
-					"[{}] Unable to open device {:?}: {err}",
+				Err(err) => Err(err.into()),
+			};
+
+			if !self
+				.attached
+				.lock()
+				.unwrap()
+				.as_ref()
+				.is_some_and(|attached| Arc::ptr_eq(attached, device))
+				|| !device.path.exists()
+			{
+				break;
+			}
+
+			if let Err(err) = result {
+				error!(
+					"[{}] Error reading device {:?}: {err}",
 					self.name,
-					self.path.display()
-				),
+					device.path.display()
+				);
 			}
-			thread::sleep(time::Duration::from_secs(1));
+			thread::sleep(Self::RETRY);
+		}
+	}
+
+	/// Prevent anything else from receiving events from the device
+	fn grab(&self, device: &mut evdev::Device) -> Result<(), Error> {
+		if self.config.device_grab(&self.name) {
+			device.grab().context("unable to grab device")?;
+			info!("[{}] Grabbed device", self.name);
 		}
+		Ok(())
 	}
 
 	fn read_events(&self, device: &mut evdev::Device) -> Result<(), Error> {
@@ -230,152 +476,155 @@ impl Device {
 	}
 
 	fn handle_event(&self, event: &InputEvent) {
-		match event.event_type() {
+		if event.event_type() == EventType::KEY && self.wake() {
+			return;
+		}
+
+		let mapping = self.config.device_mapping(&self.name);
+		let actions = match event.event_type() {
+			EventType::KEY if mapping.passthrough.contains(&event.code()) => {
+				self.passthrough(&mapping.passthrough, event);
+				Vec::new()
+			}
 			EventType::KEY => {
-				if event.value() == 1 {
-					match event.code() {
-						code @ 288..=303 => self.button_press(code - 288),
-						code @ 704..=712 => self.button_press(code - 704 + 16),
-						_ => {}
-					}
-				}
+				let actions = self
+					.gestures
+					.lock()
+					.unwrap()
+					.event(&mapping, event, Instant::now());
+
+				self.gestures_changed.notify_all();
+				actions
 			}
-			EventType::ABSOLUTE => match event.code() {
-				0 => {
-					/* X axis */
-					match event.value().cmp(&127) {
-						Ordering::Less => {
-							self.dpad_press(Direction::Left);
-						}
-						Ordering::Greater => {
-							self.dpad_press(Direction::Right);
-						}
-						_ => {}
-					}
-				}
-				1 => {
-					/* Y axis */
-					match event.value().cmp(&127) {
-						Ordering::Less => self.dpad_press(Direction::Up),
-						Ordering::Greater => {
-							self.dpad_press(Direction::Down);
+			EventType::ABSOLUTE => {
+				let mut axes = self.axes.lock().unwrap();
+				let previous = axes.get(&event.code()).copied().unwrap_or_default();
+
+				match mapping.axis(event.code(), event.value(), previous) {
+					Some((zone, action)) => {
+						axes.insert(event.code(), zone);
+
+						/* Only leaving the centre is activity, not noise from the axis */
+						if zone != previous && zone != Zone::Centre && self.wake() {
+							Vec::new()
+						} else {
+							action.cloned().into_iter().collect()
 						}
-						_ => {}
 					}
+					None => Vec::new(),
 				}
-				_ => {}
-			},
-			_ => {}
+			}
+			_ => Vec::new(),
+		};
+
+		for action in actions {
+			self.press(action);
 		}
 	}
 
-	fn button_press(&self, id: u16) {
-		debug!("[{}] Button pressed: {id}", self.name);
-		self.handler.button_press(id);
-	}
+	/// Send a key event to the rest of the system through a virtual keyboard
+	fn passthrough(&self, keys: &[u16], event: &InputEvent) {
+		let mut uinput = self.uinput.lock().unwrap();
 
-	fn dpad_press(&self, dir: Direction) {
-		debug!("[{}] D-pad pressed: {dir:?}", self.name);
-		self.handler.dpad_press(dir);
+		if uinput.as_ref().is_none_or(|(current, _)| current != keys) {
+			*uinput = match self.virtual_keyboard(keys) {
+				Ok(device) => Some((keys.to_vec(), device)),
+				Err(err) => {
+					error!("[{}] Unable to create virtual keyboard: {err}", self.name);
+					None
+				}
+			};
+		}
+
+		if let Some((_, device)) = uinput.as_mut()
+			&& let Err(err) = device.emit(&[*event])
+		{
+			error!("[{}] Unable to pass through key: {err}", self.name);
+		}
 	}
-}
 
-impl fmt::Debug for Handlers {
-	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-		f.write_str(self.as_ref())
+	fn virtual_keyboard(&self, keys: &[u16]) -> io::Result<VirtualDevice> {
+		VirtualDevice::builder()?
+			.name(&format!("status-screen {}", self.name))
+			.with_keys(
+				&keys
+					.iter()
+					.map(|code| KeyCode(*code))
+					.collect::<AttributeSet<_>>(),
+			)?
+			.build()
 	}
-}
 
-impl Konami {
-	const KONAMI_CODE: &str = "UUDDLRLRBAS";
+	fn press(&self, action: Action) {
+		debug!("[{}] Pressed: {action:?}", self.name);
+		self.browser.input_used(&self.name);
 
-	pub fn new(config: Arc<Config>, run: Arc<Mutex<Arc<Browser>>>) -> Self {
-		Self {
-			config,
-			history: Mutex::new(VecDeque::new()),
-			run,
-		}
-	}
+		let progress = {
+			let mut sequences = self.sequences.lock().unwrap();
 
-	fn add(&self, key: char) -> Option<bool> {
-		let mut history = self.history.lock().unwrap();
+			sequences.configure(self.config.sequences(&self.name));
+			sequences.input(&action, Instant::now())
+		};
 
-		history.push_back(key);
+		/* Ignore button presses while a sequence is being entered */
+		if !matches!(action, Action::Button(_)) || progress == Progress::None {
+			self.perform(action);
+		}
 
-		while history.len() > Self::KONAMI_CODE.len() {
-			history.pop_front();
+		if let Progress::Complete(sequence) = progress {
+			info!("[{}] Sequence {} entered", self.name, sequence.name);
+			self.perform(sequence.action);
 		}
+	}
 
-		if history.iter().collect::<String>() == Self::KONAMI_CODE[0..history.len()] {
-			debug!("Konami code {}/{}", history.len(), Self::KONAMI_CODE.len());
+	fn perform(&self, action: Action) {
+		match action {
+			Action::Button(id) => self.handler.button_press(id),
+			Action::Dpad(dir) => self.handler.dpad_press(dir),
+			action => self.actions.perform(&action),
+		}
+	}
 
-			if history.len() == Self::KONAMI_CODE.len() {
-				history.clear();
-				self.entered();
-				Some(true)
-			} else {
-				None
-			}
+	/// Resume the display if it's sleeping (and restart the idle timeout),
+	/// ignoring the event that woke it
+	fn wake(&self) -> bool {
+		if self.idle.activity() {
+			info!("[{}] Display resumed by input", self.name);
+			true
 		} else {
-			while history.len() > 1 {
-				history.pop_front();
-			}
-			Some(false)
+			false
 		}
 	}
+}
 
-	fn entered(&self) {
-		info!("Konami code entered");
-
-		if let Ok(command) = self.config.konami_command() {
-			execute(self.run.clone(), &command);
-		}
+impl fmt::Debug for Handlers {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(self.as_ref())
 	}
 }
 
 impl Navigation {
-	fn new(browser: Arc<Browser>, config: Arc<Config>, run: Arc<Mutex<Arc<Browser>>>) -> Self {
-		Self {
-			browser,
-			konami: Konami::new(config, run),
-		}
+	fn new(browser: Arc<Browser>) -> Self {
+		Self { browser }
 	}
 }
 
 impl Handler for Navigation {
 	fn button_press(&self, id: u16) {
-		match self.konami.add(match id {
-			0 => 'X',
-			1 => 'A',
-			2 => 'B',
-			3 => 'Y',
-			9 => 'S',
-			_ => '*',
-		}) {
-			Some(false) => match id {
-				0 => self.browser.user_press("x"),     /* X */
-				1 => self.browser.user_press("a"),     /* A */
-				2 => self.browser.user_press("b"),     /* B */
-				3 => self.browser.user_press("y"),     /* Y */
-				4 => self.browser.goto_previous_tab(), /* left bumper */
-				5 => self.browser.goto_next_tab(),     /* right bumper */
-				8 => self.browser.reload_tab(),        /* select */
-				9 => self.browser.pause(),             /* start */
-				_ => {}
-			},
-			None => { /* Ignore button presses when konami code is being entered */ }
-			Some(true) => { /* Konami code entered */ }
-		};
+		match id {
+			0 => self.browser.user_press("x"),     /* X */
+			1 => self.browser.user_press("a"),     /* A */
+			2 => self.browser.user_press("b"),     /* B */
+			3 => self.browser.user_press("y"),     /* Y */
+			4 => self.browser.goto_previous_tab(), /* left bumper */
+			5 => self.browser.goto_next_tab(),     /* right bumper */
+			8 => self.browser.reload_tab(),        /* select */
+			9 => self.browser.pause(),             /* start */
+			_ => {}
+		}
 	}
 
 	fn dpad_press(&self, dir: Direction) {
-		self.konami.add(match dir {
-			Direction::Up => 'U',
-			Direction::Down => 'D',
-			Direction::Left => 'L',
-			Direction::Right => 'R',
-		});
-
 		match dir {
 			Direction::Up => self.browser.user_press("Up"),
 			Direction::Down => self.browser.user_press("Down"),
@@ -397,7 +646,7 @@ impl Tabs {
 
 impl Handler for Tabs {
 	fn button_press(&self, id: u16) {
-		if let Ok(name) = self.config.tabs_key(id) {
+		if let Some(name) = self.config.tabs_key(id) {
 			if let Some(command) = name.strip_prefix("!") {
 				execute(self.run.clone(), command);
 			} else {
@@ -426,7 +675,7 @@ impl Timers {
 
 impl Handler for Timers {
 	fn button_press(&self, id: u16) {
-		if let Ok(name) = self.config.timers_key(id) {
+		if let Some(name) = self.config.timers_key(id) {
 			self.browser.goto_by_name("timers", true);
 			self.time_since_last.reset(&name);
 		}
@@ -435,88 +684,520 @@ impl Handler for Timers {
 	fn dpad_press(&self, _dir: Direction) {}
 }
 
-const CLIPS_DIR : &str = "~/clips";
-
-impl Clip {
-    pub fn show(run: Arc<Mutex<Arc<Browser>>>, mqtt_topic: String, mqtt_msg: String) {
-        match mqtt_topic.as_str() {
-            "clip/play" => {
-                let path = format!("{}/{}", CLIPS_DIR, mqtt_msg);
-                info!("[CLIP] Searching for {}...", path);
-                execute(run, format!("DISPLAY=:0 mpv {}", path).as_str());
-                // if std::fs::exists(&mqtt_msg).unwrap() {
-                //     let md = std::fs::metadata(&mqtt_msg).unwrap();
-                //     if md.is_file() {
-                //         info!("[CLIP] Found file at {}, playing!", path);
-                //         // Execute as soon as given
-
-                //     }
-                // }
-            }
-            _ => {
-                error!("[CLIP] Unrecognized clip mqtt topic: {}", &mqtt_topic);
-            }
-        }
-    }
+impl Actions {
+	fn new(
+		browser: Arc<Browser>,
+		mqtt: Arc<Mqtt>,
+		run: Arc<Mutex<Arc<Browser>>>,
+		time_since_last: Arc<TimeSinceLast>,
+	) -> Self {
+		Self {
+			browser,
+			mqtt,
+			run,
+			time_since_last,
+		}
+	}
+
+	fn perform(&self, action: &Action) {
+		match action {
+			Action::Next => self.browser.goto_next_tab(),
+			Action::Previous => self.browser.goto_previous_tab(),
+			Action::Reload => self.browser.reload_tab(),
+			Action::Pause => self.browser.pause(),
+			Action::Resume => self.browser.resume(),
+			Action::Sleep => {
+				info!("sending display to sleep");
+				self.browser.display_sleep();
+			}
+			Action::Key(keys) => self.browser.user_press(keys),
+			Action::Goto(tab) => {
+				self.browser.goto_by_name(tab, false);
+			}
+			Action::Run(command) => execute(self.run.clone(), command),
+			Action::Timer(name) => {
+				self.browser.goto_by_name("timers", true);
+				self.time_since_last.reset(name);
+			}
+			Action::Clip(name) => {
+				self.mqtt.publish(
+					format!("{}/play", self.mqtt.topics().clip),
+					QoS::AtLeastOnce,
+					false,
+					name.as_str(),
+				);
+			}
+			Action::Publish(topic, payload) => {
+				self.mqtt
+					.publish(topic, QoS::AtLeastOnce, false, payload.as_str());
+			}
+			Action::Button(_) | Action::Dpad(_) => { /* Handled by the keyboard */ }
+		}
+	}
+}
+
+impl Remote {
+	fn new(
+		browser: Arc<Browser>,
+		config: Arc<Config>,
+		run: Arc<Mutex<Arc<Browser>>>,
+		time_since_last: Arc<TimeSinceLast>,
+		idle: Arc<Idle>,
+	) -> Self {
+		Self {
+			browser,
+			config,
+			run,
+			time_since_last,
+			idle,
+		}
+	}
+
+	fn start(self: &Arc<Self>, mqtt: &Mqtt) {
+		let self_copy = self.clone();
+		let prefix = mqtt.topics().command("");
+		let messages = mqtt.subscribe(&format!("{prefix}#"), QoS::AtLeastOnce);
+
+		thread::spawn(move || {
+			for msg in messages {
+				if let Some(command) = msg.topic.strip_prefix(&prefix) {
+					self_copy.handle(command, &String::from_utf8_lossy(&msg.payload));
+				}
+			}
+		});
+	}
+
+	fn handle(&self, command: &str, payload: &str) {
+		info!("[MQTT] Command {command} {payload:?}");
+
+		match command {
+			"goto" => {
+				self.browser.goto_by_name(payload, false);
+			}
+			"next" => self.browser.goto_next_tab(),
+			"previous" => self.browser.goto_previous_tab(),
+			"reload" => self.browser.reload_tab(),
+			"pause" => self.browser.pause(),
+			"resume" => self.browser.resume(),
+			"paused" if payload.eq_ignore_ascii_case("ON") => self.browser.pause(),
+			"paused" if payload.eq_ignore_ascii_case("OFF") => self.browser.resume(),
+			"display" if payload.eq_ignore_ascii_case("ON") => {
+				self.idle.activity();
+			}
+			"display" if payload.eq_ignore_ascii_case("OFF") => self.browser.display_sleep(),
+			"timer" if !self.config.timer_configured(payload) => {
+				warn!("[MQTT] Timer {payload} not configured")
+			}
+			"timer" => {
+				self.browser.goto_by_name("timers", true);
+				self.time_since_last.reset(payload);
+			}
+			"action" => match self.config.action_command(payload) {
+				Some(command) => execute(self.run.clone(), &command),
+				None => warn!("[MQTT] Action {payload} not configured"),
+			},
+			_ => warn!("[MQTT] Unknown command: {command}"),
+		}
+	}
 }
 
 impl Idle {
-	pub fn new(config: &Config, browser: Arc<Browser>, run: Arc<Mutex<Arc<Browser>>>) -> Arc<Self> {
-		let client = match config.mqtt_hostname() {
-			Ok(hostname) => {
-				let mut options = MqttOptions::new("status-screen-idle", hostname, 1883);
-
-				options.set_keep_alive(Duration::from_secs(60));
-
-				let (client, mut connection) = rumqttc::Client::new(options, 10);
-				client
-					.subscribe("sensor/global/presence".to_string(), QoS::ExactlyOnce)
-					.unwrap();
-                client
-                    .subscribe("clip/#".to_string(), QoS::AtLeastOnce)
-                    .unwrap();
-
-				thread::spawn(move || {
-					for notification in connection.iter() {
-						trace!("idle MQTT received: {notification:?}");
-
-						if notification.is_err() {
-							thread::sleep(Duration::from_secs(1));
-							continue;
-						}
+	pub fn new(browser: Arc<Browser>, config: Arc<Config>) -> Arc<Self> {
+		Arc::new(Self {
+			browser,
+			config,
+			state: Mutex::new(SleepState::new(Instant::now())),
+			changed: Condvar::new(),
+		})
+	}
 
-						let Event::Incoming(Incoming::Publish(msg)) = notification.unwrap() else {
-							continue;
-						};
-
-                        if msg.topic.as_str().starts_with("clip/") {
-                            info!("[CLIP] Attempting to play clip from MQTT...");
-                            Clip::show(run.clone(), String::from(msg.topic.as_str()), String::from_utf8(msg.payload.to_vec()).unwrap());
-                            continue;
-                        }
-						if msg.topic.as_str() != "sensor/global/presence" {
-							continue;
-						}
+	pub fn start(self: &Arc<Self>, mqtt: &Mqtt) {
+		let self_copy = self.clone();
+		let messages = mqtt.subscribe(&mqtt.topics().presence, QoS::ExactlyOnce);
 
-						let payload = String::from_utf8(msg.payload.to_vec()).unwrap();
-						if payload == "empty" {
-							info!("sending display to sleep");
-							browser.display_sleep();
-						} else {
-							info!("resuming display");
-							browser.display_resume();
-						}
-					}
-				});
+		thread::spawn(move || {
+			for msg in messages {
+				self_copy.presence(&msg.payload);
+			}
+		});
+
+		let self_copy = self.clone();
 
-				Some(client)
+		thread::spawn(move || self_copy.run());
+	}
+
+	fn run(&self) {
+		let mut state = self.state.lock().unwrap();
+
+		loop {
+			let never = self.config.sleep_never().is_some_and(|schedule| {
+				schedule.active(Local::now().naive_local(), self.browser.space_open())
+			});
+			let timing = Timing {
+				delay: self.config.sleep_delay(),
+				awake: self.config.sleep_awake(),
+				idle: self.config.sleep_idle(),
+			};
+			let (action, timeout) = state.update(
+				self.browser.display_sleeping(),
+				never,
+				timing,
+				Instant::now(),
+			);
+
+			self.apply(action);
+			state = self.changed.wait_timeout(state, timeout).unwrap().0;
+		}
+	}
+
+	/// The display has been used locally or remotely, returns true if it
+	/// was resumed
+	pub fn activity(&self) -> bool {
+		let action = self
+			.state
+			.lock()
+			.unwrap()
+			.activity(self.browser.display_sleeping(), Instant::now());
+		let resumed = action == Some(sleep::Action::Resume);
+
+		self.apply(action);
+		self.changed.notify_all();
+		resumed
+	}
+
+	fn apply(&self, action: Option<sleep::Action>) {
+		match action {
+			Some(sleep::Action::Sleep) => {
+				info!("sending display to sleep");
+				self.browser.display_sleep();
 			}
-			Err(err) => {
-				warn!("MQTT not configured: {err}");
-				None
+			Some(sleep::Action::Resume) => {
+				info!("resuming display");
+				self.browser.display_resume();
 			}
+			None => {}
+		}
+	}
+
+	fn presence(&self, payload: &[u8]) {
+		let Some(present) = self.config.presence_payload().present(payload) else {
+			warn!(
+				"Unrecognised presence payload: {:?}",
+				String::from_utf8_lossy(payload)
+			);
+			return;
+		};
+
+		self.browser.set_space_open(present);
+
+		let action = self.state.lock().unwrap().presence(
+			present,
+			self.browser.display_sleeping(),
+			Instant::now(),
+		);
+
+		self.apply(action);
+		self.changed.notify_all();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::path::PathBuf;
+	use std::sync::{Arc, Mutex};
+	use std::time::{Duration, Instant};
+
+	use evdev::{EventType, InputEvent};
+
+	use crate::backend::fake::Fake;
+	use crate::config::Config;
+	use crate::hotplug::{DeviceInfo, Event};
+	use crate::mqtt::Mqtt;
+	use crate::output::{Browser, Mode, TimeSinceLast};
+	use crate::sleep;
+
+	use super::{Actions, Device, Handlers, Idle, Remote, Tabs};
+
+	fn remote() -> (Remote, Arc<Browser>, Fake) {
+		let config = Config::from_toml(
+			"[keyboards]\nmain = \"/dev/input/event0\"\n\
+			[urls]\nbuses = \"http://localhost/buses\"\nmap = \"http://localhost/map\"\n\
+			printers = \"http://localhost/printers\"\n",
+		);
+		let fake = Fake::default();
+		let browser = Browser::with_backend(false, config.clone(), Box::new(fake.clone()));
+		let run = Arc::new(Mutex::new(browser.clone()));
+
+		(
+			Remote::new(
+				browser.clone(),
+				config.clone(),
+				run,
+				TimeSinceLast::new(Mqtt::new(&config).unwrap()),
+				Idle::new(browser.clone(), config.clone()),
+			),
+			browser,
+			fake,
+		)
+	}
+
+	#[test]
+	fn remote_commands() {
+		let (remote, browser, fake) = remote();
+
+		remote.handle("goto", "printers");
+		remote.handle("previous", "");
+		remote.handle("next", "");
+		remote.handle("reload", "");
+		assert_eq!(
+			fake.take(),
+			[
+				"press 1 Ctrl+3",
+				"press 3 Ctrl+2",
+				"press 2 Ctrl+3",
+				"press 3 Ctrl+r"
+			]
+		);
+		assert_eq!(browser.status().mode, Mode::Held);
+
+		remote.handle("pause", "");
+		assert_eq!(browser.status().mode, Mode::Paused);
+
+		remote.handle("resume", "");
+		assert_eq!(browser.status().mode, Mode::Autoscroll);
+
+		remote.handle("paused", "ON");
+		assert_eq!(browser.status().mode, Mode::Paused);
+
+		remote.handle("paused", "OFF");
+		assert_eq!(browser.status().mode, Mode::Autoscroll);
+
+		remote.handle("paused", "maybe");
+		assert_eq!(browser.status().mode, Mode::Autoscroll);
+	}
+
+	#[test]
+	fn remote_ignores_invalid_commands() {
+		let (remote, browser, fake) = remote();
+
+		remote.handle("goto", "missing");
+		remote.handle("action", "missing");
+		remote.handle("timer", "missing");
+		remote.handle("unknown", "");
+		assert!(fake.take().is_empty());
+		assert_eq!(browser.status().tab, Browser::FIRST_TAB);
+	}
+
+	fn device(name: &str, config: &str) -> (Arc<Device>, Arc<Browser>, Fake) {
+		let config = Config::from_toml(&format!(
+			"[keyboards]\n{name} = \"/dev/input/status-screen-test\"\n\
+			[urls]\nbuses = \"http://localhost/buses\"\nmap = \"http://localhost/map\"\n\
+			printers = \"http://localhost/printers\"\n{config}"
+		));
+		let fake = Fake::default();
+		let browser = Browser::with_backend(false, config.clone(), Box::new(fake.clone()));
+		let run = Arc::new(Mutex::new(browser.clone()));
+		let mqtt = Mqtt::new(&config).unwrap();
+
+		(
+			Device::new(
+				name,
+				config.clone(),
+				browser.clone(),
+				Idle::new(browser.clone(), config.clone()),
+				Arc::new(Actions::new(
+					browser.clone(),
+					mqtt.clone(),
+					run.clone(),
+					TimeSinceLast::new(mqtt),
+				)),
+				Handlers::from(Tabs::new(browser.clone(), config, run)),
+			),
+			browser,
+			fake,
+		)
+	}
+
+	#[test]
+	fn input_wakes_display() {
+		let (device, browser, fake) = device(
+			"tabs",
+			"[tabs]\n0 = \"map\"\n\
+			[display]\npower = \"command\"\nsleep = \"true\"\nresume = \"true\"\n",
+		);
+		let press = InputEvent::new(EventType::KEY.0, 288, 1);
+
+		browser.display_sleep();
+		device.handle_event(&press);
+		assert!(!browser.display_sleeping());
+		assert!(fake.take().is_empty());
+
+		device.handle_event(&press);
+		assert_eq!(fake.take(), ["press 1 Ctrl+2"]);
+
+		/* Unmapped keys and releases also wake the display */
+		browser.display_sleep();
+		device.handle_event(&InputEvent::new(EventType::KEY.0, 400, 1));
+		assert!(!browser.display_sleeping());
+
+		browser.display_sleep();
+		device.handle_event(&InputEvent::new(EventType::KEY.0, 288, 0));
+		assert!(!browser.display_sleeping());
+		assert!(fake.take().is_empty());
+	}
+
+	#[test]
+	fn axis_noise_is_not_activity() {
+		let (device, browser, fake) = device(
+			"tabs",
+			"[devices.tabs.axes.2]\ncentre = 128\nthreshold = 64\nhigh = \"key Down\"\n\
+			[display]\npower = \"command\"\nsleep = \"true\"\nresume = \"true\"\n",
+		);
+		let axis = |value| device.handle_event(&InputEvent::new(EventType::ABSOLUTE.0, 2, value));
+		let timing = sleep::Timing {
+			delay: Duration::ZERO,
+			awake: Duration::ZERO,
+			idle: Some(Duration::from_secs(60)),
+		};
+		let at = Instant::now() + Duration::from_secs(30);
+		let update = || {
+			device
+				.idle
+				.state
+				.lock()
+				.unwrap()
+				.update(false, false, timing, at)
+		};
+		let before = update();
+
+		/* Noise in the centre zone doesn't restart the idle timeout */
+		axis(130);
+		axis(126);
+		axis(180);
+		axis(70);
+		assert_eq!(update(), before);
+
+		browser.display_sleep();
+		axis(140);
+		axis(128);
+		assert!(browser.display_sleeping());
+
+		/* Leaving the centre wakes the display without performing the action */
+		axis(200);
+		assert!(!browser.display_sleeping());
+		assert!(fake.take().is_empty());
+		assert_ne!(update(), before);
+
+		axis(128);
+		axis(200);
+		assert_eq!(fake.take(), ["press 1 Down"]);
+	}
+
+	#[test]
+	fn device_mapping() {
+		let (device, _, fake) = device(
+			"tabs",
+			"[tabs]\n0 = \"map\"\n\
+			[devices.tabs.keys]\n288 = \"button 0\"\n304 = \"goto printers\"\n\
+			[devices.tabs.axes.2]\ncentre = 128\nthreshold = 64\nhigh = \"key Down\"\n\
+			[devices.tabs.hats.0]\nleft = \"previous\"\n",
+		);
+		let event = |type_: EventType, code, value| {
+			device.handle_event(&InputEvent::new(type_.0, code, value));
+		};
+
+		event(EventType::KEY, 288, 1);
+		event(EventType::KEY, 288, 0);
+		event(EventType::KEY, 289, 1);
+		event(EventType::KEY, 304, 1);
+		assert_eq!(fake.take(), ["press 1 Ctrl+2", "press 2 Ctrl+3"]);
+
+		event(EventType::ABSOLUTE, 2, 150);
+		event(EventType::ABSOLUTE, 2, 200);
+		event(EventType::ABSOLUTE, 2, 255);
+		event(EventType::ABSOLUTE, 2, 128);
+		event(EventType::ABSOLUTE, 2, 192);
+		assert_eq!(fake.take(), ["press 3 Down", "press 3 Down"]);
+
+		event(EventType::ABSOLUTE, 16, -1);
+		event(EventType::ABSOLUTE, 16, 0);
+		event(EventType::ABSOLUTE, 16, 1);
+		event(EventType::ABSOLUTE, 17, 1);
+		assert_eq!(fake.take(), ["press 3 Ctrl+2"]);
+	}
+
+	#[test]
+	fn device_gestures() {
+		let (device, _, fake) = device(
+			"tabs",
+			"[devices.tabs.keys]\n288 = \"key a\"\n289 = \"key b\"\n\
+			[devices.tabs.chords]\n\"289+288\" = \"goto printers\"\n",
+		);
+		let event = |code, value| {
+			device.handle_event(&InputEvent::new(EventType::KEY.0, code, value));
+		};
+
+		event(288, 1);
+		event(289, 1);
+		event(288, 0);
+		event(289, 0);
+		assert_eq!(fake.take(), ["press 1 Ctrl+3"]);
+
+		event(288, 1);
+		event(288, 2);
+		assert_eq!(fake.take(), Vec::<String>::new());
+		event(288, 0);
+		assert_eq!(fake.take(), ["press 3 a"]);
+	}
+
+	#[test]
+	fn device_sequences() {
+		let (device, _, fake) = device(
+			"tabs",
+			"[tabs]\n2 = \"map\"\n\
+			[sequences.secret]\nkeyboard = \"tabs\"\ninputs = [\"button 0\", \"button 1\"]\n\
+			action = \"goto printers\"\n",
+		);
+		let press = |code| {
+			device.handle_event(&InputEvent::new(EventType::KEY.0, code, 1));
+		};
+
+		press(288);
+		assert_eq!(fake.take(), Vec::<String>::new());
+		press(289);
+		assert_eq!(fake.take(), ["press 1 Ctrl+3"]);
+		press(290);
+		assert_eq!(fake.take(), ["press 3 Ctrl+2"]);
+	}
+
+	#[test]
+	fn hotplug_devices() {
+		let (device, _, _) = device("main", "");
+		let gamepad = DeviceInfo {
+			path: PathBuf::from("/dev/input/status-screen-test"),
+			links: Vec::new(),
+			name: Some("usb gamepad".to_owned()),
+			vendor: Some(0x0810),
+			product: Some(0xe501),
+			serial: None,
+		};
+		let keyboard = DeviceInfo {
+			path: PathBuf::from("/dev/input/status-screen-test-2"),
+			vendor: Some(0x16c0),
+			..gamepad.clone()
 		};
 
-		Arc::new(Self { _client: client })
+		assert_eq!(device.hotplug(&Event::Added(keyboard.clone())), None);
+		assert_eq!(
+			device.hotplug(&Event::Added(gamepad.clone())).as_deref(),
+			Some(&gamepad)
+		);
+		assert_eq!(device.hotplug(&Event::Added(gamepad.clone())), None);
+		assert_eq!(device.hotplug(&Event::Removed(keyboard)), None);
+		assert_eq!(
+			device.hotplug(&Event::Removed(gamepad.clone())).as_deref(),
+			Some(&gamepad)
+		);
+		assert_eq!(device.hotplug(&Event::Removed(gamepad)), None);
 	}
 }
@@ -14,9 +14,22 @@
  * You should have received a copy of the GNU General Public License
  * along with this program.  If not, see <http://www.gnu.org/licenses/>.
  */
+mod backend;
+mod clip;
 mod config;
+mod devtools;
+mod display;
+mod gesture;
+mod homeassistant;
+mod hotplug;
+mod http;
 mod input;
+mod mapping;
+mod mqtt;
 mod output;
+mod sequence;
+mod sleep;
+mod wayland;
 
 use std::process::ExitCode;
 
@@ -34,12 +47,40 @@ fn main() -> Result<ExitCode, Error> {
 		.init()
 		.unwrap();
 
-	let config = config::Config::new(&args);
+	if args.check_config {
+		return Ok(if config::Config::check(&args) {
+			ExitCode::SUCCESS
+		} else {
+			ExitCode::FAILURE
+		});
+	}
+
+	if args.learn {
+		input::learn();
+		return Ok(ExitCode::SUCCESS);
+	}
+
+	let config = config::Config::new(&args)?;
+	let mqtt = mqtt::Mqtt::new(&config)?;
 	let browser = output::Browser::new(&args, config.clone());
-	let time_since_last = output::TimeSinceLast::new(&config);
-	let input = input::Input::new(config.clone(), browser.clone(), time_since_last.clone())?;
+	let time_since_last = output::TimeSinceLast::new(mqtt.clone());
+	let state_publisher = output::StatePublisher::new(mqtt.clone(), browser.clone());
+	let input = input::Input::new(
+		config.clone(),
+		browser.clone(),
+		mqtt.clone(),
+		time_since_last.clone(),
+	);
+
+	if let Some(listen) = config.http_listen() {
+		http::Http::new(config.clone(), browser.clone(), time_since_last.clone()).start(&listen);
+	}
 
 	input.start();
+	state_publisher.start();
+	clip::ClipPlayer::new(config.clone(), browser.clone(), mqtt.clone()).start();
+	homeassistant::HomeAssistant::start(config.clone(), mqtt.clone());
+	mqtt.start();
 	config.start();
 	browser.run();
 	Ok(ExitCode::FAILURE)
@@ -0,0 +1,288 @@
+/*
+ * Copyright 2025  Simon Arlott
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use std::{str::FromStr, time::Duration};
+
+use anyhow::{Error, anyhow};
+use indexmap::IndexMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum Direction {
+	Up,
+	Down,
+	Left,
+	Right,
+}
+
+/// What to do when an input is used
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+	Next,
+	Previous,
+	Reload,
+	Pause,
+	Resume,
+	/// Turn the display off
+	Sleep,
+	/// Press keys on the current tab
+	Key(String),
+	/// Go to a tab
+	Goto(String),
+	/// Run a shell command
+	Run(String),
+	/// Reset a timer
+	Timer(String),
+	/// Play a video clip
+	Clip(String),
+	/// Publish an MQTT message (topic and payload)
+	Publish(String, String),
+	/// Button of the keyboard (as configured in [tabs] or [timers])
+	Button(u16),
+	/// D-pad of the keyboard
+	Dpad(Direction),
+}
+
+/// Bindings of the key codes and absolute axes of an input device
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mapping {
+	pub keys: IndexMap<u16, Action>,
+	pub axes: IndexMap<u16, Axis>,
+	/// Keys held down for at least `long_press`
+	pub long: IndexMap<u16, Action>,
+	/// Keys pressed again within `double_press` of being released
+	pub double: IndexMap<u16, Action>,
+	/// Pairs of keys held down together (lowest code first)
+	pub chords: IndexMap<(u16, u16), Action>,
+	pub long_press: Duration,
+	pub double_press: Duration,
+	/// Keys that are sent to the rest of the system instead
+	pub passthrough: Vec<u16>,
+}
+
+/// Absolute axis with an action at each end
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Axis {
+	pub centre: i32,
+	/// Distance from the centre to activate the action
+	pub threshold: i32,
+	pub low: Option<Action>,
+	pub high: Option<Action>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Zone {
+	#[default]
+	Centre,
+	Low,
+	High,
+}
+
+impl FromStr for Action {
+	type Err = Error;
+
+	fn from_str(value: &str) -> Result<Self, Error> {
+		let (name, arg) = match value.split_once(' ') {
+			Some((name, arg)) => (name, Some(arg.trim())),
+			None => (value, None),
+		};
+
+		Ok(match (name, arg) {
+			("next", None) => Self::Next,
+			("previous", None) => Self::Previous,
+			("reload", None) => Self::Reload,
+			("pause", None) => Self::Pause,
+			("resume", None) => Self::Resume,
+			("sleep", None) => Self::Sleep,
+			("key", Some(keys)) => Self::Key(keys.to_owned()),
+			("goto", Some(tab)) => Self::Goto(tab.to_owned()),
+			("run", Some(command)) => Self::Run(command.to_owned()),
+			("timer", Some(timer)) => Self::Timer(timer.to_owned()),
+			("clip", Some(clip)) => Self::Clip(clip.to_owned()),
+			("publish", Some(message)) => match message.split_once(' ') {
+				Some((topic, payload)) => {
+					Self::Publish(topic.to_owned(), payload.trim().to_owned())
+				}
+				None => Self::Publish(message.to_owned(), String::new()),
+			},
+			("button", Some(id)) => {
+				Self::Button(id.parse().map_err(|_| anyhow!("invalid button: {id:?}"))?)
+			}
+			("dpad", Some(direction)) => Self::Dpad(
+				direction
+					.parse()
+					.map_err(|_| anyhow!("invalid direction: {direction:?}"))?,
+			),
+			_ => return Err(anyhow!("invalid action: {value:?}")),
+		})
+	}
+}
+
+impl Mapping {
+	/* ABS_HAT0X */
+	const HAT_FIRST: u16 = 16;
+	pub const LONG_PRESS: Duration = Duration::from_millis(800);
+	pub const DOUBLE_PRESS: Duration = Duration::from_millis(300);
+
+	pub fn key(&self, code: u16) -> Option<&Action> {
+		self.keys.get(&code)
+	}
+
+	pub fn long(&self, code: u16) -> Option<&Action> {
+		self.long.get(&code)
+	}
+
+	pub fn double(&self, code: u16) -> Option<&Action> {
+		self.double.get(&code)
+	}
+
+	pub fn chord(&self, first: u16, second: u16) -> Option<&Action> {
+		self.chords.get(&(first.min(second), first.max(second)))
+	}
+
+	/// The key has gestures other than being pressed
+	pub fn gestures(&self, code: u16) -> bool {
+		self.long.contains_key(&code)
+			|| self.double.contains_key(&code)
+			|| self.chords.keys().any(|(a, b)| *a == code || *b == code)
+	}
+
+	/// Find the zone of the axis value and the action to perform (only when
+	/// it has changed from the previous zone)
+	pub fn axis(&self, code: u16, value: i32, previous: Zone) -> Option<(Zone, Option<&Action>)> {
+		let axis = self.axes.get(&code)?;
+		let zone = axis.zone(value);
+
+		Some((
+			zone,
+			match zone {
+				_ if zone == previous => None,
+				Zone::Centre => None,
+				Zone::Low => axis.low.as_ref(),
+				Zone::High => axis.high.as_ref(),
+			},
+		))
+	}
+
+	/// Axes of a hat switch (left/right and up/down)
+	pub fn hat_axes(hat: u16) -> (u16, u16) {
+		let x = Self::HAT_FIRST + hat * 2;
+
+		(x, x + 1)
+	}
+}
+
+impl Axis {
+	pub fn zone(&self, value: i32) -> Zone {
+		if value <= self.centre - self.threshold {
+			Zone::Low
+		} else if value >= self.centre + self.threshold {
+			Zone::High
+		} else {
+			Zone::Centre
+		}
+	}
+}
+
+impl Default for Mapping {
+	/// Buttons and D-pad of the gamepad and Pico keyboards
+	fn default() -> Self {
+		let dpad = |low, high| Axis {
+			centre: 127,
+			threshold: 1,
+			low: Some(Action::Dpad(low)),
+			high: Some(Action::Dpad(high)),
+		};
+
+		Self {
+			keys: (288..=303)
+				.map(|code| (code, Action::Button(code - 288)))
+				.chain((704..=712).map(|code| (code, Action::Button(code - 704 + 16))))
+				.collect(),
+			axes: IndexMap::from([
+				(0, dpad(Direction::Left, Direction::Right)),
+				(1, dpad(Direction::Up, Direction::Down)),
+			]),
+			long: IndexMap::new(),
+			double: IndexMap::new(),
+			chords: IndexMap::new(),
+			long_press: Self::LONG_PRESS,
+			double_press: Self::DOUBLE_PRESS,
+			passthrough: Vec::new(),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Action, Direction, Mapping, Zone};
+
+	#[test]
+	fn parse_actions() {
+		assert_eq!("next".parse::<Action>().unwrap(), Action::Next);
+		assert_eq!(
+			"key Ctrl+Tab".parse::<Action>().unwrap(),
+			Action::Key("Ctrl+Tab".to_owned())
+		);
+		assert_eq!(
+			"run ./life.sh --random".parse::<Action>().unwrap(),
+			Action::Run("./life.sh --random".to_owned())
+		);
+		assert_eq!(
+			"dpad left".parse::<Action>().unwrap(),
+			Action::Dpad(Direction::Left)
+		);
+		assert_eq!("button 17".parse::<Action>().unwrap(), Action::Button(17));
+		assert_eq!(
+			"publish hacklab/horn {\"sound\": \"tada\"}"
+				.parse::<Action>()
+				.unwrap(),
+			Action::Publish(
+				"hacklab/horn".to_owned(),
+				"{\"sound\": \"tada\"}".to_owned()
+			)
+		);
+		assert!("next tab".parse::<Action>().is_err());
+		assert!("goto".parse::<Action>().is_err());
+		assert!("dpad sideways".parse::<Action>().is_err());
+		assert!("explode".parse::<Action>().is_err());
+	}
+
+	#[test]
+	fn default_mapping() {
+		let mapping = Mapping::default();
+
+		assert_eq!(mapping.key(288), Some(&Action::Button(0)));
+		assert_eq!(mapping.key(705), Some(&Action::Button(17)));
+		assert_eq!(mapping.key(304), None);
+
+		let left = Action::Dpad(Direction::Left);
+		let right = Action::Dpad(Direction::Right);
+
+		assert_eq!(
+			mapping.axis(0, 0, Zone::Centre),
+			Some((Zone::Low, Some(&left)))
+		);
+		assert_eq!(mapping.axis(0, 0, Zone::Low), Some((Zone::Low, None)));
+		assert_eq!(mapping.axis(0, 127, Zone::Low), Some((Zone::Centre, None)));
+		assert_eq!(
+			mapping.axis(0, 255, Zone::Low),
+			Some((Zone::High, Some(&right)))
+		);
+		assert_eq!(mapping.axis(2, 0, Zone::Centre), None);
+	}
+}
@@ -0,0 +1,239 @@
+/*
+ * Copyright 2025  Simon Arlott
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use std::{
+	fs,
+	sync::{
+		Arc, Mutex,
+		atomic::{AtomicBool, Ordering},
+		mpsc::{self, Receiver, Sender},
+	},
+	thread,
+	time::Duration,
+};
+
+use anyhow::{Context, Error};
+use log::{debug, error, info, trace, warn};
+use rumqttc::{Event, Incoming, LastWill, MqttOptions, Publish, QoS, Transport};
+
+use crate::config::{Config, MqttTopics};
+
+/// Connection to the MQTT broker shared by everything that uses MQTT
+#[derive(derive_more::Debug)]
+pub struct Mqtt {
+	topics: MqttTopics,
+	#[debug("{:?}", client.is_some())]
+	client: Option<rumqttc::Client>,
+	#[debug(skip)]
+	connection: Mutex<Option<rumqttc::Connection>>,
+	connected: AtomicBool,
+	subscriptions: Mutex<Vec<Subscription>>,
+	/// Notified every time the connection is established
+	connect_subscribers: Mutex<Vec<Sender<()>>>,
+}
+
+#[derive(Debug)]
+struct Subscription {
+	filter: String,
+	qos: QoS,
+	tx: Sender<Publish>,
+}
+
+impl Mqtt {
+	/// Outstanding requests before publishing blocks
+	const CAPACITY: usize = 100;
+
+	pub fn new(config: &Config) -> Result<Arc<Self>, Error> {
+		let topics = config.mqtt_topics();
+		let (client, connection) = match config.mqtt() {
+			Some(mqtt) => {
+				let mut options = MqttOptions::new(mqtt.client_id, mqtt.hostname, mqtt.port);
+
+				options.set_keep_alive(Duration::from_secs(mqtt.keep_alive));
+				options.set_last_will(LastWill::new(
+					topics.availability(),
+					"offline",
+					QoS::AtLeastOnce,
+					true,
+				));
+
+				if let Some(username) = mqtt.username {
+					options.set_credentials(username, mqtt.password.unwrap_or_default());
+				}
+
+				if let Some(tls_ca) = mqtt.tls_ca {
+					let ca = fs::read(&tls_ca)
+						.with_context(|| format!("Unable to read {:?}", tls_ca.display()))?;
+
+					options.set_transport(Transport::tls(ca, None, None));
+				}
+
+				let (client, connection) = rumqttc::Client::new(options, Self::CAPACITY);
+
+				(Some(client), Some(connection))
+			}
+			None => {
+				warn!("MQTT not configured");
+				(None, None)
+			}
+		};
+
+		Ok(Arc::new(Self {
+			topics,
+			client,
+			connection: Mutex::new(connection),
+			connected: AtomicBool::new(false),
+			subscriptions: Mutex::new(Vec::new()),
+			connect_subscribers: Mutex::new(Vec::new()),
+		}))
+	}
+
+	pub fn enabled(&self) -> bool {
+		self.client.is_some()
+	}
+
+	pub fn topics(&self) -> &MqttTopics {
+		&self.topics
+	}
+
+	pub fn start(self: &Arc<Self>) {
+		let Some(mut connection) = self.connection.lock().unwrap().take() else {
+			return;
+		};
+		let (tx, rx) = mpsc::channel();
+		let self_copy = self.clone();
+
+		/* Make requests outside of the connection thread so that it isn't blocked */
+		thread::spawn(move || {
+			while rx.recv().is_ok() {
+				self_copy.connected();
+			}
+		});
+
+		let self_copy = self.clone();
+
+		thread::spawn(move || {
+			for notification in connection.iter() {
+				trace!("MQTT received: {notification:?}");
+
+				match notification {
+					Ok(Event::Incoming(Incoming::ConnAck(_))) => {
+						info!("MQTT connected");
+						let _ = tx.send(());
+					}
+					Ok(Event::Incoming(Incoming::Publish(msg))) => self_copy.received(msg),
+					Ok(_) => {}
+					Err(err) => {
+						if self_copy.connected.swap(false, Ordering::Relaxed) {
+							error!("MQTT connection lost: {err}");
+						}
+						thread::sleep(Duration::from_secs(1));
+					}
+				}
+			}
+		});
+	}
+
+	/// Restore subscriptions and availability after connecting
+	fn connected(&self) {
+		let subscriptions = self.subscriptions.lock().unwrap();
+
+		self.connected.store(true, Ordering::Relaxed);
+
+		for subscription in subscriptions.iter() {
+			self.request_subscribe(subscription);
+		}
+
+		drop(subscriptions);
+		self.publish(self.topics.availability(), QoS::AtLeastOnce, true, "online");
+
+		self.connect_subscribers
+			.lock()
+			.unwrap()
+			.retain(|subscriber| subscriber.send(()).is_ok());
+	}
+
+	fn received(&self, msg: Publish) {
+		for subscription in self.subscriptions.lock().unwrap().iter() {
+			if rumqttc::matches(&msg.topic, &subscription.filter) {
+				let _ = subscription.tx.send(msg.clone());
+			}
+		}
+	}
+
+	/// Receive messages matching a topic filter, subscribing again every
+	/// time the connection is established
+	pub fn subscribe(&self, filter: &str, qos: QoS) -> Receiver<Publish> {
+		let (tx, rx) = mpsc::channel();
+		let subscription = Subscription {
+			filter: filter.to_owned(),
+			qos,
+			tx,
+		};
+		let mut subscriptions = self.subscriptions.lock().unwrap();
+
+		if self.connected.load(Ordering::Relaxed) {
+			self.request_subscribe(&subscription);
+		}
+
+		subscriptions.push(subscription);
+		rx
+	}
+
+	fn request_subscribe(&self, subscription: &Subscription) {
+		if let Some(client) = &self.client {
+			debug!("MQTT subscribe: {}", subscription.filter);
+
+			if let Err(err) = client.subscribe(subscription.filter.clone(), subscription.qos) {
+				error!("MQTT subscribe to {} failed: {err}", subscription.filter);
+			}
+		}
+	}
+
+	/// Receive a notification every time the connection is established
+	pub fn subscribe_connect(&self) -> Receiver<()> {
+		let (tx, rx) = mpsc::channel();
+
+		if self.connected.load(Ordering::Relaxed) {
+			let _ = tx.send(());
+		}
+
+		self.connect_subscribers.lock().unwrap().push(tx);
+		rx
+	}
+
+	pub fn publish<T: Into<String>, V: Into<Vec<u8>>>(
+		&self,
+		topic: T,
+		qos: QoS,
+		retain: bool,
+		payload: V,
+	) -> bool {
+		let Some(client) = &self.client else {
+			return false;
+		};
+		let topic = topic.into();
+
+		match client.publish(topic.clone(), qos, retain, payload) {
+			Ok(()) => true,
+			Err(err) => {
+				error!("MQTT publish to {topic} failed: {err}");
+				false
+			}
+		}
+	}
+}
@@ -17,109 +17,132 @@
 
 use core::time;
 use std::{
-	collections::HashMap,
-	process::Command,
+	collections::{HashMap, HashSet},
 	sync::{
 		Arc, Condvar, Mutex, MutexGuard,
 		atomic::{AtomicBool, Ordering},
+		mpsc::{self, Receiver, Sender},
 	},
 	thread,
 	time::{Duration, Instant},
 };
 
-use anyhow::{Error, anyhow};
-use libxdo::{Search, Window, XDo};
+use chrono::{DateTime, FixedOffset, Local, NaiveDateTime, TimeDelta};
+use indexmap::IndexMap;
 use log::{debug, error, info, trace, warn};
-use rumqttc::MqttOptions;
-use sha2::{Digest, Sha256};
-use xcap::Monitor;
+use rumqttc::QoS;
+use serde::Serialize;
 
+use crate::backend::{Backend, Cdp, Session, Wayland, X11};
 use crate::config::{CommandLineArgs, Config, Page};
+use crate::mqtt::Mqtt;
 
 #[derive(Debug)]
 pub struct Browser {
 	kiosk: bool,
-	pages: Vec<Page>,
-	tabs: HashMap<String, usize>,
 	state: Mutex<BrowserState>,
 	sleep: Condvar,
 	config: Arc<Config>,
-	hands: Mutex<Hands>,
-	eyes: Eyes,
+	backend: Box<dyn Backend>,
 	display_sleeping: AtomicBool,
+	space_open: AtomicBool,
+	subscribers: Mutex<Vec<Sender<()>>>,
 }
 
 #[derive(Debug)]
 struct BrowserState {
+	pages: IndexMap<String, Page>,
 	tab: usize,
 	changed: Instant,
 	held: bool,
 	paused: bool,
+	startup: bool,
 	content: Vec<(String, Option<Instant>)>,
+	/// Current weights of the tabs for smooth weighted round-robin
+	credit: HashMap<String, i64>,
+	/// Name of the last input device used
+	input: Option<String>,
 }
 
-#[derive(derive_more::Debug)]
-struct Hands {
-	no_search: bool,
-	use_xdotool: bool,
-	script: String,
-	#[debug("XDo")]
-	xdo: XDo,
-	window: Option<Window>,
+/// Current state of the browser
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Status {
+	pub tab: usize,
+	pub name: String,
+	pub url: String,
+	pub mode: Mode,
+	/// Seconds until the next autoscroll
+	pub next_autoscroll: Option<u64>,
+	pub display_sleeping: bool,
+	pub input: Option<String>,
 }
 
-#[derive(derive_more::Debug)]
-struct Eyes {
-	monitors: Vec<Monitor>,
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, strum::AsRefStr)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum Mode {
+	Autoscroll,
+	Held,
+	Paused,
 }
 
-#[derive(derive_more::Debug)]
+/// Publish the state of the browser to MQTT
+#[derive(Debug)]
+pub struct StatePublisher {
+	browser: Arc<Browser>,
+	mqtt: Arc<Mqtt>,
+	published: Mutex<HashMap<&'static str, String>>,
+}
+
+#[derive(Debug)]
 pub struct TimeSinceLast {
-	#[debug("{:?}", client.is_some())]
-	client: Option<rumqttc::Client>,
+	mqtt: Arc<Mqtt>,
 }
 
 impl Browser {
-	const FIRST_TAB: usize = 1;
+	pub const FIRST_TAB: usize = 1;
 
 	pub fn new(args: &CommandLineArgs, config: Arc<Config>) -> Arc<Self> {
-		let mut pages = Vec::<Page>::new();
-		let mut tabs = HashMap::new();
-
-		if let Ok(config_urls) = config.browser_urls() {
-			for (name, page) in config_urls {
-				if tabs.insert(name.clone(), tabs.len() + 1).is_none() {
-					pages.push(page);
-				} else {
-					warn!("Duplicate url {name} ignored");
+		let session = Session::detect();
+
+		info!("Session type: {session:?}");
+
+		let backend: Box<dyn Backend> = match (args.devtools, session) {
+			(Some(port), _) => Box::new(Cdp::new(session, args.kiosk, port)),
+			(None, Session::X11) => Box::new(X11::new(args.kiosk, args.xdotool, args.no_search)),
+			(None, Session::Wayland) => {
+				if args.xdotool || args.no_search {
+					warn!("Options for X11 key presses are ignored on Wayland");
 				}
+				Box::new(Wayland::new(args.kiosk))
 			}
-		}
+		};
 
+		Self::with_backend(args.kiosk, config, backend)
+	}
+
+	pub fn with_backend(kiosk: bool, config: Arc<Config>, backend: Box<dyn Backend>) -> Arc<Self> {
 		Arc::new(Self {
-			kiosk: args.kiosk,
-			pages,
-			tabs,
-			state: Mutex::new(BrowserState::default()),
+			kiosk,
+			state: Mutex::new(BrowserState::new(config.browser_urls())),
 			sleep: Condvar::new(),
 			config,
-			hands: Mutex::new(Hands::new(args.xdotool, args.no_search)),
-			eyes: Eyes::default(),
+			backend,
 			display_sleeping: AtomicBool::new(false),
+			space_open: AtomicBool::new(true),
+			subscribers: Mutex::new(Vec::new()),
 		})
 	}
 
 	pub fn run(self: &Arc<Browser>) {
-		let urls: Vec<&String> = self.pages.iter().map(|page| &page.url).collect();
+		let urls: Vec<String> = {
+			let state = self.state.lock().unwrap();
 
-		let mut command = Command::new("chromium-browser");
-		if self.kiosk {
-			command.arg("--kiosk");
-		}
-		command.arg("--disable-web-security").arg("--temp-profile");
-		command.args(urls);
+			state.pages.values().map(|page| page.url.clone()).collect()
+		};
+
+		self.backend.launch(&urls).expect("Browser failed to start");
 
-		let mut child = command.spawn().expect("Browser failed to start");
 		if self.kiosk {
 			let self_copy = self.clone();
 
@@ -127,7 +150,22 @@ impl Browser {
 				self_copy.autoscroll();
 			});
 		}
-		child.wait().expect("Browser failed to run");
+
+		let self_copy = self.clone();
+		let config_changes = self.config.subscribe();
+
+		thread::spawn(move || {
+			for sections in config_changes {
+				if ["urls", "reload", "schedule", "dwell", "weight"]
+					.iter()
+					.any(|section| sections.contains(section))
+				{
+					self_copy.update_pages();
+				}
+			}
+		});
+
+		self.backend.wait().expect("Browser failed to run");
 		error!("Browser stopped");
 	}
 
@@ -137,50 +175,127 @@ impl Browser {
 		self.activity(&mut state);
 	}
 
+	/// Record which input device was used
+	pub fn input_used(&self, device: &str) {
+		let mut state = self.state.lock().unwrap();
+
+		if state.input.as_deref() != Some(device) {
+			state.input = Some(device.to_owned());
+			self.notify();
+		}
+	}
+
 	fn activity(&self, state: &mut MutexGuard<BrowserState>) {
 		state.changed = Instant::now();
 		self.sleep.notify_all();
+		self.notify();
+	}
+
+	/// Receive a notification every time the state may have changed
+	pub fn subscribe(&self) -> Receiver<()> {
+		let (tx, rx) = mpsc::channel();
+
+		self.subscribers.lock().unwrap().push(tx);
+		rx
+	}
+
+	fn notify(&self) {
+		self.subscribers
+			.lock()
+			.unwrap()
+			.retain(|subscriber| subscriber.send(()).is_ok());
 	}
 
 	fn autoscroll(&self) {
 		let mut state = self.state.lock().unwrap();
-		let mut multiplier = 2; /* startup is slow */
 
 		loop {
-			let now = Instant::now();
-			let next = state.changed
-				+ if state.paused {
-					self.config.autoscroll_pause()
-				} else if state.held {
-					self.config.autoscroll_hold()
-				} else {
-					self.config.autoscroll_delay() * multiplier
-				};
-
-			if now < next {
-				let timeout = next - now;
-
+			if let Some(timeout) =
+				self.autoscroll_next(&mut state, Instant::now(), Local::now().naive_local())
+			{
 				trace!("Sleep for {timeout:?}");
 
 				state = self.sleep.wait_timeout(state, timeout).unwrap().0;
-				continue;
 			}
+		}
+	}
 
-			multiplier = 1;
+	/// Go to the next scheduled tab if it's time to do so, otherwise return
+	/// how long to wait
+	fn autoscroll_next(
+		&self,
+		state: &mut MutexGuard<BrowserState>,
+		now: Instant,
+		time: NaiveDateTime,
+	) -> Option<Duration> {
+		let next = self.autoscroll_due(state);
+
+		if now < next {
+			return Some(next - now);
+		}
 
-			let tab = self.next_tab_id(&state);
+		state.startup = false;
 
-			debug!("Go to next tab (autoscroll)");
-			if let Some((duration, reload)) = self.tab_content(&mut state) {
-				trace!("Tab {} has been static for {duration:?}", state.tab);
+		let tab = self.next_scheduled_tab_id(state, time);
 
-				if reload {
-					debug!("Reload tab (auto)");
-					self.press("Ctrl+r");
-				}
+		debug!("Go to next tab (autoscroll)");
+		if let Some((duration, reload)) = self.tab_content(state, now) {
+			trace!("Tab {} has been static for {duration:?}", state.tab);
+
+			if reload {
+				debug!("Reload tab (auto)");
+				self.reload(state);
 			}
-			self.unpause(&mut state);
-			self.change_tab(&mut state, tab);
+		}
+		self.unpause(state);
+		self.change_tab(state, tab);
+		None
+	}
+
+	/// When the next autoscroll is due
+	fn autoscroll_due(&self, state: &MutexGuard<BrowserState>) -> Instant {
+		let delay = state.pages[state.tab - Self::FIRST_TAB]
+			.dwell
+			.unwrap_or_else(|| self.config.autoscroll_delay());
+
+		state.changed
+			+ if state.paused {
+				self.config.autoscroll_pause()
+			} else if state.held {
+				self.config.autoscroll_hold()
+			} else if state.startup {
+				/* startup is slow */
+				delay * 2
+			} else {
+				delay
+			}
+	}
+
+	pub fn status(&self) -> Status {
+		let state = self.state.lock().unwrap();
+		let (name, page) = state.pages.get_index(state.tab - Self::FIRST_TAB).unwrap();
+
+		Status {
+			tab: state.tab,
+			name: name.clone(),
+			url: self
+				.backend
+				.active_url(state.tab)
+				.unwrap_or_else(|| page.url.clone()),
+			mode: if state.paused {
+				Mode::Paused
+			} else if state.held {
+				Mode::Held
+			} else {
+				Mode::Autoscroll
+			},
+			next_autoscroll: self.kiosk.then(|| {
+				self.autoscroll_due(&state)
+					.saturating_duration_since(Instant::now())
+					.as_secs()
+			}),
+			display_sleeping: self.display_sleeping.load(Ordering::Relaxed),
+			input: state.input.clone(),
 		}
 	}
 
@@ -208,18 +323,19 @@ impl Browser {
 		let mut state = self.state.lock().unwrap();
 
 		debug!("Reload tab");
-		self.press("Ctrl+r");
+		self.reload(&state);
 		self.activity(&mut state);
 	}
 
-	pub fn goto_by_name(&self, name: &str, sync: bool) {
+	/// Go to a tab by name, returning false if it doesn't exist
+	pub fn goto_by_name(&self, name: &str, sync: bool) -> bool {
 		debug!("Go to tab {name}");
 
-		match self.tabs.get(name) {
-			Some(tab) => {
-				let mut state = self.state.lock().unwrap();
+		let mut state = self.state.lock().unwrap();
 
-				if self.change_tab(&mut state, *tab) {
+		match state.pages.get_index_of(name) {
+			Some(index) => {
+				if self.change_tab(&mut state, index + Self::FIRST_TAB) {
 					self.unpause(&mut state);
 					self.hold(&mut state);
 
@@ -227,9 +343,11 @@ impl Browser {
 						thread::sleep(time::Duration::from_millis(100));
 					}
 				}
+				true
 			}
 			None => {
 				warn!("Tab {name} not found");
+				false
 			}
 		}
 	}
@@ -238,17 +356,30 @@ impl Browser {
 		let mut state = self.state.lock().unwrap();
 
 		debug!("Press keys on browser: {keys} (user)");
-		self.press(keys);
+		self.press(&state, keys);
 		self.hold(&mut state);
 		self.activity(&mut state);
 	}
 
-	fn press(&self, keys: &str) {
-		let mut hands = self.hands.lock().unwrap();
-
+	fn press(&self, state: &MutexGuard<BrowserState>, keys: &str) {
 		trace!("Press keys on browser: {keys}");
 
-		hands.press(keys);
+		if let Err(err) = self.backend.press(state.tab, keys) {
+			error!("Unable to send keys {keys:?} to browser: {err}");
+		}
+	}
+
+	fn reload(&self, state: &MutexGuard<BrowserState>) {
+		if let Err(err) = self.backend.reload(state.tab) {
+			error!("Unable to reload tab {}: {err}", state.tab);
+		}
+	}
+
+	pub fn hold_tab(&self) {
+		let mut state = self.state.lock().unwrap();
+
+		debug!("Hold tab");
+		self.hold(&mut state);
 	}
 
 	fn hold(&self, state: &mut MutexGuard<BrowserState>) {
@@ -272,6 +403,18 @@ impl Browser {
 		self.activity(&mut state);
 	}
 
+	pub fn resume(&self) {
+		let mut state = self.state.lock().unwrap();
+
+		if state.held || state.paused {
+			state.held = false;
+			state.paused = false;
+			info!("Resumed");
+		}
+
+		self.activity(&mut state);
+	}
+
 	fn unpause(&self, state: &mut MutexGuard<BrowserState>) {
 		if state.held {
 			state.held = false;
@@ -283,82 +426,89 @@ impl Browser {
 		}
 	}
 
-	fn last_tab(&self) -> usize {
-		self.tabs.len()
+	fn last_tab(&self, state: &MutexGuard<BrowserState>) -> usize {
+		state.pages.len()
 	}
 
-	fn tab_count(&self) -> usize {
-		self.tabs.len()
+	fn tab_count(&self, state: &MutexGuard<BrowserState>) -> usize {
+		state.pages.len()
 	}
 
 	fn previous_tab_id(&self, state: &MutexGuard<BrowserState>) -> usize {
 		if state.tab == Self::FIRST_TAB {
-			self.last_tab()
+			self.last_tab(state)
 		} else {
 			state.tab - 1
 		}
 	}
 
 	fn next_tab_id(&self, state: &MutexGuard<BrowserState>) -> usize {
-		if state.tab == self.last_tab() {
+		self.tab_after(state, state.tab)
+	}
+
+	fn tab_after(&self, state: &MutexGuard<BrowserState>, tab: usize) -> usize {
+		if tab >= self.last_tab(state) {
 			Self::FIRST_TAB
 		} else {
-			state.tab + 1
+			tab + 1
 		}
 	}
 
-	fn change_tab(&self, state: &mut MutexGuard<BrowserState>, tab: usize) -> bool {
-		const KEY_NEXT_TAB: &str = "Ctrl+Tab";
-		const KEY_PREVIOUS_TAB: &str = "Ctrl+Shift+Tab";
-		const DIRECT_TABS: usize = 8;
-		const KEY_LAST_TAB: &str = "Ctrl+9";
-		let key_last_direct_tab: &str = &("Ctrl+".to_owned() + &DIRECT_TABS.to_string());
-		let current = state.tab;
-		let previous = self.previous_tab_id(state);
-		let next = self.next_tab_id(state);
-		let last = self.last_tab();
-		let direct_tabs = Self::FIRST_TAB..=DIRECT_TABS;
-		let indirect_tabs = (DIRECT_TABS + 1)..last;
-
-		if tab == current {
-			/* Nothing to do */
-		} else if direct_tabs.contains(&tab) {
-			self.press(&("Ctrl+".to_owned() + &tab.to_string()));
-		} else if tab == last {
-			self.press(KEY_LAST_TAB);
-		} else if tab == next {
-			self.press(KEY_NEXT_TAB);
-		} else if tab == previous {
-			self.press(KEY_PREVIOUS_TAB);
-		} else if indirect_tabs.contains(&tab) {
-			let (keys_from_current_tab, key_from_current_tab) = if tab < current {
-				(current - tab, KEY_PREVIOUS_TAB)
-			} else {
-				(tab - current, KEY_NEXT_TAB)
-			};
-			let keys_from_last_direct_tab = 1 + (tab - DIRECT_TABS);
-			let keys_from_last_tab = 1 + (last - tab);
-			let mut keys = Vec::new();
-
-			if keys_from_current_tab <= keys_from_last_direct_tab.min(keys_from_last_tab) {
-				/* Navigate from current tab */
-				keys.resize(keys_from_current_tab, key_from_current_tab);
-			} else if keys_from_last_direct_tab <= keys_from_last_tab {
-				/* Navigate from last direct tab */
-				keys.push(key_last_direct_tab);
-				keys.resize(keys_from_last_direct_tab, KEY_NEXT_TAB);
+	/// Choose the next tab that is in schedule using smooth weighted
+	/// round-robin (preferring the tabs that follow the current tab), or stay
+	/// on the current tab
+	fn next_scheduled_tab_id(
+		&self,
+		state: &mut MutexGuard<BrowserState>,
+		time: NaiveDateTime,
+	) -> usize {
+		let space_open = self.space_open.load(Ordering::Relaxed);
+		let mut tab = state.tab;
+		let mut scheduled = Vec::new();
+
+		for _ in 0..self.tab_count(state) {
+			tab = self.tab_after(state, tab);
+
+			let (name, page) = state.pages.get_index(tab - Self::FIRST_TAB).unwrap();
+
+			if page.schedule.active(time, space_open) {
+				scheduled.push((tab, name.clone(), page.weight as i64));
 			} else {
-				/* Navigate from last tab */
-				keys.push(KEY_LAST_TAB);
-				keys.resize(keys_from_last_tab, KEY_PREVIOUS_TAB);
+				trace!("Tab {tab} not in schedule");
 			}
+		}
 
-			self.press(&keys.join(" "));
-		} else {
-			panic!(
-				"Unable to navigate to invalid tab {tab} from tab {}",
+		let total: i64 = scheduled.iter().map(|(_, _, weight)| weight).sum();
+		let mut next: Option<(usize, &String, i64)> = None;
+
+		for (tab, name, weight) in scheduled.iter() {
+			let credit = state.credit.entry(name.clone()).or_default();
+
+			*credit += weight;
+
+			if next.is_none_or(|(_, _, best)| *credit > best) {
+				next = Some((*tab, name, *credit));
+			}
+		}
+
+		match next {
+			Some((tab, name, _)) => {
+				*state.credit.get_mut(name).unwrap() -= total;
+				tab
+			}
+			None => {
+				debug!("No tabs in schedule");
 				state.tab
-			);
+			}
+		}
+	}
+
+	fn change_tab(&self, state: &mut MutexGuard<BrowserState>, tab: usize) -> bool {
+		let tab_count = self.tab_count(state);
+
+		if let Err(err) = self.backend.switch_tab(state.tab, tab, tab_count) {
+			error!("Unable to change to tab {tab}: {err}");
+			return false;
 		}
 
 		self.activity(state);
@@ -371,14 +521,25 @@ impl Browser {
 		}
 	}
 
-	fn tab_content(&self, state: &mut MutexGuard<BrowserState>) -> Option<(Duration, bool)> {
-		self.eyes.see().map(|content| {
-			let now = Instant::now();
+	/// Change to a tab to modify it, returning false if that wasn't possible
+	fn select_tab(&self, state: &mut MutexGuard<BrowserState>, tab: usize) -> bool {
+		self.change_tab(state, tab) || state.tab == tab
+	}
+
+	fn tab_content(
+		&self,
+		state: &mut MutexGuard<BrowserState>,
+		now: Instant,
+	) -> Option<(Duration, bool)> {
+		self.backend.capture().map(|content| {
 			let tab = state.tab;
+			let reload = state.pages[tab - Browser::FIRST_TAB].reload;
+
+			let tab_count = self.tab_count(state);
 
 			state
 				.content
-				.resize_with(self.tab_count(), || ("".to_owned(), None));
+				.resize_with(tab_count, || ("".to_owned(), None));
 
 			let tab_state = &mut state.content[tab - Browser::FIRST_TAB];
 
@@ -394,7 +555,7 @@ impl Browser {
 				.1
 				.map(|last_change| now - last_change)
 				.and_then(|duration| {
-					self.pages[tab - Browser::FIRST_TAB].reload.map(|reload| {
+					reload.map(|reload| {
 						let reload = if duration >= reload {
 							tab_state.1 = None;
 							true
@@ -409,13 +570,139 @@ impl Browser {
 		})
 	}
 
+	/// Open, close, navigate and move tabs to match the configured urls
+	fn update_pages(&self) {
+		let mut state = self.state.lock().unwrap();
+		let pages = self.config.browser_urls();
+		let tab_count = self.tab_count(&state);
+		/* Pages that couldn't be updated because their tab couldn't be selected */
+		let mut skipped = HashSet::new();
+
+		state
+			.content
+			.resize_with(tab_count, || ("".to_owned(), None));
+
+		/* Close removed tabs, starting from the end so that tab ids don't change */
+		for index in (0..state.pages.len()).rev() {
+			let name = state.pages.get_index(index).unwrap().0.clone();
+
+			if !pages.contains_key(&name) {
+				info!("Close tab {name}");
+				if !self.select_tab(&mut state, index + Self::FIRST_TAB) {
+					error!("Unable to close tab {name}");
+					continue;
+				}
+				if let Err(err) = self.backend.close_tab(state.tab) {
+					error!("Unable to close tab {}: {err}", state.tab);
+					continue;
+				}
+
+				state.pages.shift_remove_index(index);
+				state.content.remove(index);
+
+				/* The next tab becomes active, unless this was the last tab */
+				if state.tab > self.last_tab(&state) {
+					state.tab = self.last_tab(&state);
+				}
+			}
+		}
+
+		/* Navigate tabs with changed urls */
+		for (name, page) in pages.iter() {
+			if let Some((index, _, current)) = state.pages.get_full(name)
+				&& current.url != page.url
+			{
+				info!("Navigate tab {name} to {}", page.url);
+				if !self.select_tab(&mut state, index + Self::FIRST_TAB) {
+					error!("Unable to navigate tab {name}");
+					skipped.insert(name.clone());
+					continue;
+				}
+				if let Err(err) = self.backend.navigate(state.tab, &page.url) {
+					error!("Unable to navigate tab {}: {err}", state.tab);
+					skipped.insert(name.clone());
+					continue;
+				}
+
+				state.content[index] = ("".to_owned(), None);
+			}
+		}
+
+		/* Open new tabs at the end */
+		for (name, page) in pages.iter() {
+			if !state.pages.contains_key(name) {
+				info!("Open tab {name} at {}", page.url);
+				if let Err(err) = self.backend.open_tab(state.tab, &page.url) {
+					error!("Unable to open tab: {err}");
+					continue;
+				}
+
+				state.pages.insert(name.clone(), page.clone());
+				state.content.push(("".to_owned(), None));
+				state.tab = self.last_tab(&state);
+			}
+		}
+
+		/* Move tabs into the configured order */
+		for (index, name) in pages.keys().enumerate() {
+			/* Tabs that couldn't be opened are missing */
+			let Some(current) = state.pages.get_index_of(name) else {
+				continue;
+			};
+
+			if current > index {
+				debug!("Move tab {name} from {current} to {index}");
+				if !self.select_tab(&mut state, current + Self::FIRST_TAB) {
+					error!("Unable to move tab {name}");
+					continue;
+				}
+				if let Err(err) = self
+					.backend
+					.move_tab(current + Self::FIRST_TAB, index + Self::FIRST_TAB)
+				{
+					error!("Unable to move tab {}: {err}", state.tab);
+					continue;
+				}
+				state.tab = index + Self::FIRST_TAB;
+
+				state.pages.move_index(current, index);
+				let content = state.content.remove(current);
+				state.content.insert(index, content);
+			}
+		}
+
+		for (name, page) in pages {
+			if !skipped.contains(&name)
+				&& let Some(current) = state.pages.get_mut(&name)
+			{
+				*current = page;
+			}
+		}
+		state.credit.clear();
+		self.activity(&mut state);
+	}
+
+	pub fn space_open(&self) -> bool {
+		self.space_open.load(Ordering::Relaxed)
+	}
+
+	pub fn set_space_open(&self, open: bool) {
+		if self.space_open.swap(open, Ordering::Relaxed) != open {
+			info!("Space {}", if open { "open" } else { "closed" });
+		}
+	}
+
+	pub fn display_sleeping(&self) -> bool {
+		self.display_sleeping.load(Ordering::Relaxed)
+	}
+
 	pub fn display_sleep(&self) {
-		Command::new("sh")
-			.arg("-c")
-			.arg("tvservice -o")
-			.output()
-			.unwrap();
+		if let Err(err) = self.config.display_power().sleep() {
+			error!("Unable to put display to sleep: {err}");
+			return;
+		}
 		self.display_sleeping.store(true, Ordering::Relaxed);
+		self.notify();
 	}
 
 	pub fn display_resume(&self) {
@@ -423,178 +710,675 @@ impl Browser {
 			return;
 		}
 
-		// this will also restart this program, so don't block
-		Command::new("sh")
-			.arg("-c")
-			.arg("tvservice -p && sleep 1 && systemctl restart --no-block --user x")
-			.output()
-			.unwrap();
+		if let Err(err) = self.config.display_power().resume() {
+			error!("Unable to resume display: {err}");
+		}
 		self.display_sleeping.store(false, Ordering::Relaxed);
+		self.user_activity();
 	}
 }
 
-impl Default for BrowserState {
-	fn default() -> Self {
+impl BrowserState {
+	fn new(pages: IndexMap<String, Page>) -> Self {
 		Self {
+			pages,
 			tab: Browser::FIRST_TAB,
 			changed: Instant::now(),
 			held: false,
 			paused: false,
+			startup: true,
 			content: Vec::new(),
+			credit: HashMap::new(),
+			input: None,
 		}
 	}
 }
 
-impl Hands {
-	const BROWSER_WINDOW_CLASS_REGEX: &str = "^chromium(-browser)?$";
-
-	pub fn new(use_xdotool: bool, no_search: bool) -> Self {
-		let mut script = "xdotool key ".to_string();
+impl StatePublisher {
+	pub fn new(mqtt: Arc<Mqtt>, browser: Arc<Browser>) -> Arc<Self> {
+		Arc::new(Self {
+			browser,
+			mqtt,
+			published: Mutex::new(HashMap::new()),
+		})
+	}
 
-		if !no_search {
-			script += "--window $(xdotool search --onlyvisible --class '";
-			script += Self::BROWSER_WINDOW_CLASS_REGEX;
-			script += "') ";
+	pub fn start(self: &Arc<Self>) {
+		if !self.mqtt.enabled() {
+			return;
 		}
 
-		Self {
-			use_xdotool,
-			script,
-			xdo: XDo::new(None).unwrap(),
-			no_search,
-			window: None,
+		let self_copy = self.clone();
+		let changes = self.browser.subscribe();
+
+		thread::spawn(move || {
+			self_copy.publish();
+
+			while changes.recv().is_ok() {
+				/* Publish once for a burst of changes */
+				while changes.try_recv().is_ok() {}
+				self_copy.publish();
+			}
+		});
+
+		let self_copy = self.clone();
+		let connects = self.mqtt.subscribe_connect();
+
+		thread::spawn(move || {
+			for _ in connects {
+				/* The broker may have lost the retained messages */
+				self_copy.published.lock().unwrap().clear();
+				self_copy.publish();
+			}
+		});
+	}
+
+	fn publish(&self) {
+		let mut published = self.published.lock().unwrap();
+
+		for (name, value) in Self::topics(&self.browser.status(), Local::now().fixed_offset()) {
+			if published.get(name) == Some(&value) {
+				continue;
+			}
+
+			debug!("Publish state {name}: {value}");
+
+			if self.mqtt.publish(
+				self.mqtt.topics().state(name),
+				QoS::AtLeastOnce,
+				true,
+				value.clone(),
+			) {
+				published.insert(name, value);
+			}
 		}
 	}
 
-	pub fn press(&mut self, keys: &str) {
-		if let Err(err) = if self.use_xdotool {
-			self.press_xdotool(keys)
-		} else {
-			self.press_libxdo(keys)
-		} {
-			error!("Unable to send keys {keys:?} to browser: {err}");
+	/// Values of the state topics
+	fn topics(status: &Status, now: DateTime<FixedOffset>) -> [(&'static str, String); 5] {
+		[
+			("screen", status.name.clone()),
+			("mode", status.mode.as_ref().to_owned()),
+			(
+				"next",
+				status
+					.next_autoscroll
+					.map(|secs| {
+						(now + TimeDelta::seconds(secs as i64))
+							.to_rfc3339_opts(chrono::SecondsFormat::Secs, false)
+					})
+					.unwrap_or_default(),
+			),
+			(
+				"display",
+				if status.display_sleeping { "off" } else { "on" }.to_owned(),
+			),
+			("input", status.input.clone().unwrap_or_default()),
+		]
+	}
+}
+
+impl TimeSinceLast {
+	pub fn new(mqtt: Arc<Mqtt>) -> Arc<Self> {
+		Arc::new(Self { mqtt })
+	}
+
+	pub fn reset(&self, name: &str) {
+		if self.mqtt.enabled() {
+			info!("Reset timer: {name}");
+
+			self.mqtt.publish(
+				&self.mqtt.topics().timer_reset,
+				QoS::AtMostOnce,
+				false,
+				name,
+			);
 		}
 	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{
+		sync::Arc,
+		time::{Duration, Instant},
+	};
+
+	use chrono::{FixedOffset, NaiveDate, NaiveDateTime, TimeZone};
+
+	use crate::backend::fake::Fake;
+	use crate::config::Config;
+
+	use super::{Browser, Mode, StatePublisher};
 
-	fn press_xdotool(&mut self, keys: &str) -> Result<(), Error> {
-		Command::new("sh")
-			.arg("-c")
-			.arg(self.script.clone() + keys)
-			.output()?;
-		Ok(())
+	fn browser(tabs: usize) -> (Arc<Browser>, Fake) {
+		browser_with(tabs, "")
 	}
 
-	fn press_libxdo(&mut self, keys: &str) -> Result<(), Error> {
-		/* xdotool(1): Delay between keystrokes. Default is 12ms. */
-		const DELAY_US: u32 = 12_000;
+	fn browser_with(tabs: usize, extra: &str) -> (Arc<Browser>, Fake) {
+		let urls: String = (1..=tabs)
+			.map(|tab| format!("tab{tab} = \"http://localhost/{tab}\"\n"))
+			.collect();
+		let config = Config::from_toml(&format!(
+			"[keyboards]\nmain = \"/dev/input/event0\"\n\
+			[urls]\n{urls}\
+			[autoscroll]\ndelay = 20\nhold = 60\npause = 900\n\
+			[reload]\ntab1 = 300\n\
+			{extra}"
+		));
+		let fake = Fake::default();
+
+		(
+			Browser::with_backend(true, config, Box::new(fake.clone())),
+			fake,
+		)
+	}
+
+	/// Saturday afternoon
+	fn time() -> NaiveDateTime {
+		NaiveDate::from_ymd_opt(2025, 6, 7)
+			.unwrap()
+			.and_hms_opt(14, 0, 0)
+			.unwrap()
+	}
 
-		let window = if self.no_search {
+	#[test]
+	fn autoscroll_waits_longer_at_startup() {
+		let (browser, fake) = browser(3);
+		let mut state = browser.state.lock().unwrap();
+		let start = state.changed;
+
+		assert_eq!(
+			browser.autoscroll_next(&mut state, start + Duration::from_secs(20), time()),
+			Some(Duration::from_secs(20))
+		);
+		assert_eq!(
+			browser.autoscroll_next(&mut state, start + Duration::from_secs(40), time()),
 			None
-		} else {
-			if self.window.is_none() {
-				self.window = self
-					.xdo
-					.search_windows(Search {
-						only_visible: true,
-						window_class: Some(Self::BROWSER_WINDOW_CLASS_REGEX.to_string()),
-						limit: 1,
-						..Search::default()
-					})
-					.inspect_err(|err| error!("Unable to find browser window: {err}"))
-					.ok()
-					.and_then(|windows| {
-						if windows.is_empty() {
-							error!("No browser windows found");
-						} else if windows.len() > 2 {
-							warn!("Multiple browser windows found: {windows:?}");
-						} else {
-							trace!("Found one browser window: {}", windows[0]);
-						}
-						windows.first().copied()
-					});
-			}
+		);
+		assert_eq!(state.tab, 2);
+		assert_eq!(fake.take(), ["press 1 Ctrl+2"]);
+
+		let changed = state.changed;
+		assert_eq!(
+			browser.autoscroll_next(&mut state, changed + Duration::from_secs(5), time()),
+			Some(Duration::from_secs(15))
+		);
+		assert_eq!(
+			browser.autoscroll_next(&mut state, changed + Duration::from_secs(20), time()),
+			None
+		);
+		assert_eq!(state.tab, 3);
+		assert_eq!(fake.take(), ["press 2 Ctrl+3"]);
+	}
+
+	#[test]
+	fn autoscroll_wraps_around() {
+		let (browser, fake) = browser(2);
+		let mut state = browser.state.lock().unwrap();
+
+		state.startup = false;
+		state.tab = 2;
+
+		let changed = state.changed;
+		assert_eq!(
+			browser.autoscroll_next(&mut state, changed + Duration::from_secs(20), time()),
+			None
+		);
+		assert_eq!(state.tab, 1);
+		assert_eq!(fake.take(), ["press 2 Ctrl+1"]);
+	}
+
+	#[test]
+	fn display_sleep_failed() {
+		let (browser, _) = browser_with(
+			1,
+			"[display]\npower = \"command\"\nsleep = \"exit 1\"\nresume = \"true\"\n",
+		);
+
+		browser.display_sleep();
+		assert!(!browser.display_sleeping());
+		assert!(!browser.status().display_sleeping);
+	}
+
+	#[test]
+	fn status_active_url() {
+		let (browser, fake) = browser(2);
+
+		assert_eq!(browser.status().url, "http://localhost/1");
+
+		/* The page navigated somewhere else */
+		*fake.url.lock().unwrap() = Some("http://localhost/other".to_owned());
+		assert_eq!(browser.status().url, "http://localhost/other");
+	}
+
+	#[test]
+	fn manual_navigation_holds_autoscroll() {
+		let (browser, fake) = browser(3);
+
+		browser.goto_next_tab();
+		assert_eq!(fake.take(), ["press 1 Ctrl+2"]);
+
+		let mut state = browser.state.lock().unwrap();
+		let changed = state.changed;
+
+		assert!(state.held);
+		assert_eq!(
+			browser.autoscroll_next(&mut state, changed + Duration::from_secs(40), time()),
+			Some(Duration::from_secs(20))
+		);
+		assert_eq!(
+			browser.autoscroll_next(&mut state, changed + Duration::from_secs(60), time()),
+			None
+		);
+		assert!(!state.held);
+		assert_eq!(state.tab, 3);
+	}
+
+	fn urls(urls: &[&str]) -> String {
+		let urls: String = urls
+			.iter()
+			.map(|name| format!("{name} = \"http://localhost/{name}\"\n"))
+			.collect();
+
+		format!("[keyboards]\nmain = \"/dev/input/event0\"\n[urls]\n{urls}")
+	}
+
+	fn pages(browser: &Browser) -> Vec<String> {
+		browser
+			.state
+			.lock()
+			.unwrap()
+			.pages
+			.keys()
+			.cloned()
+			.collect()
+	}
+
+	#[test]
+	fn update_pages_add() {
+		let config = Config::from_toml(&urls(&["a", "b"]));
+		let fake = Fake::default();
+		let browser = Browser::with_backend(true, config.clone(), Box::new(fake.clone()));
+
+		config.replace_toml(&urls(&["a", "b", "c"]));
+		browser.update_pages();
+
+		let actions = fake.take();
+		assert_eq!(actions.first().unwrap(), "press 1 Ctrl+t");
+		assert_eq!(actions.last().unwrap(), "press 1 Return");
+		assert_eq!(pages(&browser), ["a", "b", "c"]);
+		assert_eq!(browser.state.lock().unwrap().tab, 3);
+	}
+
+	#[test]
+	fn update_pages_remove() {
+		let config = Config::from_toml(&urls(&["a", "b", "c"]));
+		let fake = Fake::default();
+		let browser = Browser::with_backend(true, config.clone(), Box::new(fake.clone()));
+
+		config.replace_toml(&urls(&["a", "c"]));
+		browser.update_pages();
+		assert_eq!(fake.take(), ["press 1 Ctrl+2", "press 2 Ctrl+w"]);
+		assert_eq!(pages(&browser), ["a", "c"]);
+		assert_eq!(browser.state.lock().unwrap().tab, 2);
+	}
+
+	#[test]
+	fn update_pages_reorder() {
+		let config = Config::from_toml(&urls(&["a", "b", "c"]));
+		let fake = Fake::default();
+		let browser = Browser::with_backend(true, config.clone(), Box::new(fake.clone()));
+
+		config.replace_toml(&urls(&["c", "a", "b"]));
+		browser.update_pages();
+		assert_eq!(
+			fake.take(),
+			[
+				"press 1 Ctrl+3",
+				"press 3 Ctrl+Shift+Prior Ctrl+Shift+Prior"
+			]
+		);
+		assert_eq!(pages(&browser), ["c", "a", "b"]);
+		assert_eq!(browser.state.lock().unwrap().tab, 1);
+	}
+
+	#[test]
+	fn update_pages_switch_failed() {
+		let config = Config::from_toml(&urls(&["a", "b", "c"]));
+		let fake = Fake::default();
+		let browser = Browser::with_backend(true, config.clone(), Box::new(fake.clone()));
+
+		fake.fail.lock().unwrap().push("Ctrl+2".to_owned());
+
+		/* Tab b can't be navigated, so it keeps the old url */
+		config.replace_toml(
+			"[keyboards]\nmain = \"/dev/input/event0\"\n\
+			[urls]\na = \"http://localhost/a\"\nb = \"http://localhost/new\"\n\
+			c = \"http://localhost/c\"\n",
+		);
+		browser.update_pages();
+		assert!(fake.take().is_empty());
+		assert_eq!(
+			browser.state.lock().unwrap().pages["b"].url,
+			"http://localhost/b"
+		);
+
+		/* Tab b can't be closed, so it's still there */
+		config.replace_toml(&urls(&["a", "c"]));
+		browser.update_pages();
+		assert_eq!(fake.take(), ["press 1 Ctrl+3", "press 3 Ctrl+Shift+Prior"]);
+		assert_eq!(pages(&browser), ["a", "c", "b"]);
+		assert_eq!(browser.state.lock().unwrap().tab, 2);
+	}
 
-			Some(self.window.ok_or(anyhow!("Browser window not found"))?)
+	#[test]
+	fn update_pages_backend_failed() {
+		let config = Config::from_toml(&urls(&["a", "b", "c"]));
+		let fake = Fake::default();
+		let browser = Browser::with_backend(true, config.clone(), Box::new(fake.clone()));
+		let fail = |keys: &str| *fake.fail.lock().unwrap() = vec![keys.to_owned()];
+		let content = || {
+			browser
+				.state
+				.lock()
+				.unwrap()
+				.content
+				.iter()
+				.map(|(content, _)| content.clone())
+				.collect::<Vec<_>>()
 		};
 
-		self.xdo
-			.send_keysequence(window, keys, DELAY_US)
-			.inspect_err(|_| self.window = None)?;
-		Ok(())
+		browser.update_pages();
+		for (index, content) in browser.state.lock().unwrap().content.iter_mut().enumerate() {
+			content.0 = format!("content{index}");
+		}
+
+		/* Tab c isn't closed */
+		fail("Ctrl+w");
+		config.replace_toml(&urls(&["a", "b"]));
+		browser.update_pages();
+		assert_eq!(fake.take(), ["press 1 Ctrl+3"]);
+		assert_eq!(pages(&browser), ["a", "b", "c"]);
+		assert_eq!(content(), ["content0", "content1", "content2"]);
+		assert_eq!(browser.state.lock().unwrap().tab, 3);
+
+		/* Tab d isn't opened */
+		fail("Ctrl+t");
+		config.replace_toml(&urls(&["a", "b", "c", "d"]));
+		browser.update_pages();
+		assert!(fake.take().is_empty());
+		assert_eq!(pages(&browser), ["a", "b", "c"]);
+		assert_eq!(content(), ["content0", "content1", "content2"]);
+		assert_eq!(browser.state.lock().unwrap().tab, 3);
+
+		/* Tab b isn't navigated, so it keeps the old url */
+		fail("Ctrl+l");
+		config.replace_toml(
+			"[keyboards]\nmain = \"/dev/input/event0\"\n\
+			[urls]\na = \"http://localhost/a\"\nb = \"http://localhost/new\"\n\
+			c = \"http://localhost/c\"\n",
+		);
+		browser.update_pages();
+		assert_eq!(fake.take(), ["press 3 Ctrl+2"]);
+		assert_eq!(
+			browser.state.lock().unwrap().pages["b"].url,
+			"http://localhost/b"
+		);
+		assert_eq!(content(), ["content0", "content1", "content2"]);
+
+		/* Tab c isn't moved */
+		fail("Ctrl+Shift+Prior Ctrl+Shift+Prior");
+		config.replace_toml(&urls(&["c", "a", "b"]));
+		browser.update_pages();
+		assert_eq!(fake.take(), ["press 2 Ctrl+3"]);
+		assert_eq!(pages(&browser), ["a", "b", "c"]);
+		assert_eq!(content(), ["content0", "content1", "content2"]);
+		assert_eq!(browser.state.lock().unwrap().tab, 3);
 	}
-}
 
-impl Default for Eyes {
-	fn default() -> Self {
-		Self {
-			monitors: Monitor::all().unwrap(),
+	#[test]
+	fn pause_until_timeout_or_navigation() {
+		let (browser, fake) = browser(3);
+
+		browser.pause();
+
+		{
+			let mut state = browser.state.lock().unwrap();
+			let changed = state.changed;
+
+			assert!(state.paused);
+			assert_eq!(
+				browser.autoscroll_next(&mut state, changed + Duration::from_secs(60), time()),
+				Some(Duration::from_secs(840))
+			);
 		}
+
+		/* Pressing keys doesn't unpause */
+		browser.user_press("Down");
+		assert_eq!(fake.take(), ["press 1 Down"]);
+		assert!(browser.state.lock().unwrap().paused);
+
+		browser.goto_previous_tab();
+		assert_eq!(fake.take(), ["press 1 Ctrl+3"]);
+
+		let state = browser.state.lock().unwrap();
+		assert!(!state.paused);
+		assert!(state.held);
 	}
-}
 
-impl Eyes {
-	pub fn see(&self) -> Option<String> {
-		self.monitors
-			.first()
-			.and_then(|monitor| match monitor.capture_image() {
-				Ok(image) => {
-					let mut hasher = Sha256::new();
-					hasher.update(image.as_raw());
-					let hash = hasher.finalize();
-					Some(format!("{:x}", hash))
-				}
+	#[test]
+	fn user_press_holds_autoscroll() {
+		let (browser, fake) = browser(3);
 
-				Err(err) => {
-					error!("Unable to capture image: {err}");
-					None
-				}
-			})
+		browser.user_press("x");
+		assert_eq!(fake.take(), ["press 1 x"]);
+		assert!(browser.state.lock().unwrap().held);
 	}
-}
 
-impl TimeSinceLast {
-	pub fn new(config: &Config) -> Arc<Self> {
-		let client = match config.mqtt_hostname() {
-			Ok(hostname) => {
-				let mut options = MqttOptions::new("rumqtt-sync", hostname, 1883);
+	#[test]
+	fn goto_by_name() {
+		let (browser, fake) = browser(12);
+
+		browser.goto_by_name("tab3", false);
+		browser.goto_by_name("tab3", false);
+		browser.goto_by_name("tab12", false);
+		browser.goto_by_name("tab10", false);
+		browser.goto_by_name("missing", false);
+		assert_eq!(
+			fake.take(),
+			[
+				"press 1 Ctrl+3",
+				"press 3 Ctrl+9",
+				"press 12 Ctrl+Shift+Tab Ctrl+Shift+Tab",
+			]
+		);
+		assert_eq!(browser.state.lock().unwrap().tab, 10);
+	}
 
-				options.set_keep_alive(Duration::from_secs(60));
+	#[test]
+	fn autoscroll_reloads_static_tab() {
+		let (browser, fake) = browser(2);
+		let mut state = browser.state.lock().unwrap();
+		let start = Instant::now();
+
+		*fake.screen.lock().unwrap() = Some("static".to_owned());
+		state.startup = false;
+		state.changed = start;
+
+		assert_eq!(
+			browser.tab_content(&mut state, start),
+			Some((Duration::ZERO, false))
+		);
+		assert_eq!(
+			browser.tab_content(&mut state, start + Duration::from_secs(300)),
+			Some((Duration::from_secs(300), true))
+		);
+
+		/* The timer restarts after a reload */
+		assert_eq!(
+			browser.tab_content(&mut state, start + Duration::from_secs(301)),
+			Some((Duration::ZERO, false))
+		);
+
+		*fake.screen.lock().unwrap() = Some("changed".to_owned());
+		state.tab = 1;
+		assert_eq!(
+			browser.autoscroll_next(&mut state, start + Duration::from_secs(301), time()),
+			None
+		);
+		assert_eq!(fake.take(), ["press 1 Ctrl+2"]);
+	}
 
-				let (client, mut connection) = rumqttc::Client::new(options, 10);
+	#[test]
+	fn autoscroll_skips_tabs_out_of_schedule() {
+		let (browser, fake) = browser_with(
+			4,
+			"[schedule.tab2]\ndays = [\"Mon\", \"Tue\"]\n\
+			[schedule.tab3]\nspace_open = true\n\
+			[schedule.tab4]\ntimes = [\"07:00-23:00\"]\n",
+		);
+		let mut state = browser.state.lock().unwrap();
+
+		state.startup = false;
+
+		let changed = state.changed;
+		assert_eq!(
+			browser.autoscroll_next(&mut state, changed + Duration::from_secs(20), time()),
+			None
+		);
+		assert_eq!(state.tab, 3);
 
-				thread::spawn(move || {
-					for notification in connection.iter() {
-						trace!("MQTT received: {notification:?}");
+		browser.set_space_open(false);
 
-						if notification.is_err() {
-							thread::sleep(Duration::from_secs(1));
-						}
-					}
-				});
+		let changed = state.changed;
+		assert_eq!(
+			browser.autoscroll_next(&mut state, changed + Duration::from_secs(20), time()),
+			None
+		);
+		assert_eq!(state.tab, 4);
 
-				Some(client)
-			}
-			Err(err) => {
-				warn!("MQTT not configured: {err}");
-				None
-			}
-		};
+		let changed = state.changed;
+		let night = time().date().and_hms_opt(23, 30, 0).unwrap();
+		assert_eq!(
+			browser.autoscroll_next(&mut state, changed + Duration::from_secs(20), night),
+			None
+		);
+		assert_eq!(state.tab, 1);
 
-		Arc::new(Self { client })
+		/* Stay on the current tab when nothing else is scheduled */
+		let changed = state.changed;
+		assert_eq!(
+			browser.autoscroll_next(&mut state, changed + Duration::from_secs(20), night),
+			None
+		);
+		assert_eq!(state.tab, 1);
+		assert_eq!(
+			fake.take(),
+			["press 1 Ctrl+3", "press 3 Ctrl+4", "press 4 Ctrl+1"]
+		);
+		drop(state);
+
+		/* Tabs out of schedule can still be shown manually */
+		browser.goto_by_name("tab2", false);
+		assert_eq!(fake.take(), ["press 1 Ctrl+2"]);
 	}
 
-	pub fn reset(&self, name: &str) {
-		if let Some(client) = &self.client {
-			info!("Reset timer: {name}");
+	#[test]
+	fn autoscroll_dwell_time() {
+		let (browser, _) = browser_with(3, "[dwell]\ntab2 = 60\n");
+		let mut state = browser.state.lock().unwrap();
 
-			if let Err(err) = client.publish(
-				"time-since-last/reset",
-				rumqttc::QoS::AtMostOnce,
-				false,
-				name,
-			) {
-				error!("MQTT publish failed: {err}");
-			}
+		state.startup = false;
+
+		let changed = state.changed;
+		assert_eq!(
+			browser.autoscroll_next(&mut state, changed + Duration::from_secs(20), time()),
+			None
+		);
+		assert_eq!(state.tab, 2);
+
+		let changed = state.changed;
+		assert_eq!(
+			browser.autoscroll_next(&mut state, changed + Duration::from_secs(20), time()),
+			Some(Duration::from_secs(40))
+		);
+		assert_eq!(
+			browser.autoscroll_next(&mut state, changed + Duration::from_secs(60), time()),
+			None
+		);
+		assert_eq!(state.tab, 3);
+	}
+
+	#[test]
+	fn autoscroll_weights() {
+		let (browser, _) = browser_with(3, "[weight]\ntab1 = 3\n");
+		let mut state = browser.state.lock().unwrap();
+		let mut tabs = Vec::new();
+
+		state.startup = false;
+
+		for _ in 0..10 {
+			let changed = state.changed;
+
+			browser.autoscroll_next(&mut state, changed + Duration::from_secs(20), time());
+			tabs.push(state.tab);
 		}
+
+		/* Spread out instead of showing the same tab several times in a row */
+		assert_eq!(tabs, [1, 2, 1, 3, 1, 1, 2, 1, 3, 1]);
+	}
+
+	#[test]
+	fn state_changes_are_notified() {
+		let (browser, _) = browser(3);
+		let changes = browser.subscribe();
+
+		browser.goto_next_tab();
+		assert!(changes.try_recv().is_ok());
+
+		browser.input_used("main");
+		while changes.try_recv().is_ok() {}
+		browser.input_used("main");
+		assert!(changes.try_recv().is_err());
+
+		let status = browser.status();
+		assert_eq!(status.name, "tab2");
+		assert_eq!(status.mode, Mode::Held);
+		assert_eq!(status.input.as_deref(), Some("main"));
+	}
+
+	#[test]
+	fn state_topics() {
+		let (browser, _) = browser(3);
+		let now = FixedOffset::east_opt(3600)
+			.unwrap()
+			.with_ymd_and_hms(2025, 6, 7, 14, 0, 0)
+			.unwrap();
+		let mut status = browser.status();
+
+		status.next_autoscroll = Some(40);
+		assert_eq!(
+			StatePublisher::topics(&status, now),
+			[
+				("screen", "tab1".to_owned()),
+				("mode", "autoscroll".to_owned()),
+				("next", "2025-06-07T14:00:40+01:00".to_owned()),
+				("display", "on".to_owned()),
+				("input", "".to_owned()),
+			]
+		);
+
+		status.next_autoscroll = None;
+		status.display_sleeping = true;
+		status.input = Some("tabs".to_owned());
+		assert_eq!(
+			StatePublisher::topics(&status, now)[2..],
+			[
+				("next", "".to_owned()),
+				("display", "off".to_owned()),
+				("input", "tabs".to_owned()),
+			]
+		);
 	}
 }
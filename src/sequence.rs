@@ -0,0 +1,211 @@
+/*
+ * Copyright 2025  Simon Arlott
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use std::time::{Duration, Instant};
+
+use crate::mapping::Action;
+
+/// Named sequence of inputs that performs an action when entered
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sequence {
+	pub name: String,
+	pub inputs: Vec<Action>,
+	pub action: Action,
+	/// Maximum time between inputs
+	pub timeout: Duration,
+}
+
+/// Matches inputs against all of the sequences at the same time
+///
+/// Every sequence that could be in progress is tracked separately so that
+/// sequences can overlap with each other (or themselves, e.g. "up up down"
+/// is still matched after "up up up down").
+#[derive(Debug, Default)]
+pub struct Sequences {
+	sequences: Vec<Sequence>,
+	/// Sequence index, number of inputs matched and time of the last input
+	active: Vec<(usize, usize, Instant)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Progress {
+	/// Not part of any sequence
+	None,
+	/// Part of a sequence that hasn't been completed yet
+	Partial,
+	Complete(Sequence),
+}
+
+impl Sequences {
+	/// Use the currently configured sequences, starting again if they've
+	/// changed
+	pub fn configure(&mut self, sequences: Vec<Sequence>) {
+		if self.sequences != sequences {
+			self.sequences = sequences;
+			self.active.clear();
+		}
+	}
+
+	pub fn input(&mut self, input: &Action, now: Instant) -> Progress {
+		let sequences = &self.sequences;
+
+		self.active.retain_mut(|(index, matched, last)| {
+			let sequence = &sequences[*index];
+
+			if now > *last + sequence.timeout || sequence.inputs[*matched] != *input {
+				return false;
+			}
+
+			*matched += 1;
+			*last = now;
+			true
+		});
+
+		self.active.extend(
+			sequences
+				.iter()
+				.enumerate()
+				.filter(|(_, sequence)| sequence.inputs.first() == Some(input))
+				.map(|(index, _)| (index, 1, now)),
+		);
+
+		if let Some(&(index, _, _)) = self
+			.active
+			.iter()
+			.find(|(index, matched, _)| *matched == sequences[*index].inputs.len())
+		{
+			self.active.clear();
+			Progress::Complete(sequences[index].clone())
+		} else if self.active.is_empty() {
+			Progress::None
+		} else {
+			Progress::Partial
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::{Duration, Instant};
+
+	use crate::mapping::{Action, Direction};
+
+	use super::{Progress, Sequence, Sequences};
+
+	fn sequence(name: &str, inputs: &str) -> Sequence {
+		Sequence {
+			name: name.to_owned(),
+			inputs: inputs
+				.chars()
+				.map(|input| match input {
+					'U' => Action::Dpad(Direction::Up),
+					'D' => Action::Dpad(Direction::Down),
+					'L' => Action::Dpad(Direction::Left),
+					'R' => Action::Dpad(Direction::Right),
+					'A' => Action::Button(1),
+					'B' => Action::Button(2),
+					_ => Action::Button(9),
+				})
+				.collect(),
+			action: Action::Goto(name.to_owned()),
+			timeout: Duration::from_secs(2),
+		}
+	}
+
+	/// Enter inputs one second apart, returning the names of the completed
+	/// sequences and whether each input was part of a sequence
+	fn enter(sequences: &mut Sequences, inputs: &str) -> Vec<String> {
+		let start = Instant::now();
+
+		sequence("", inputs)
+			.inputs
+			.iter()
+			.enumerate()
+			.map(
+				|(i, input)| match sequences.input(input, start + Duration::from_secs(i as u64)) {
+					Progress::None => "-".to_owned(),
+					Progress::Partial => "+".to_owned(),
+					Progress::Complete(sequence) => sequence.name,
+				},
+			)
+			.collect()
+	}
+
+	#[test]
+	fn konami() {
+		let mut sequences = Sequences::default();
+
+		sequences.configure(vec![sequence("konami", "UUDDLRLRBAS")]);
+		assert_eq!(
+			enter(&mut sequences, "UUUDDLRLRBASA"),
+			[
+				"+", "+", "+", "+", "+", "+", "+", "+", "+", "+", "+", "konami", "-"
+			]
+		);
+		assert_eq!(
+			enter(&mut sequences, "UUDDLA"),
+			["+", "+", "+", "+", "+", "-"]
+		);
+	}
+
+	#[test]
+	fn overlapping() {
+		let mut sequences = Sequences::default();
+
+		sequences.configure(vec![
+			sequence("long", "ABAB"),
+			sequence("short", "BA"),
+			sequence("prefix", "ABAS"),
+		]);
+		assert_eq!(enter(&mut sequences, "ABA"), ["+", "+", "short"]);
+		assert_eq!(enter(&mut sequences, "BBAS"), ["+", "+", "short", "-"]);
+		assert_eq!(
+			enter(&mut sequences, "AAABAS"),
+			["+", "+", "+", "+", "short", "-"]
+		);
+
+		sequences.configure(vec![sequence("long", "ABAB"), sequence("prefix", "ABAS")]);
+		assert_eq!(
+			enter(&mut sequences, "ABABAS"),
+			["+", "+", "+", "long", "+", "-"]
+		);
+		assert_eq!(
+			enter(&mut sequences, "AABAS"),
+			["+", "+", "+", "+", "prefix"]
+		);
+	}
+
+	#[test]
+	fn timeout() {
+		let mut sequences = Sequences::default();
+		let start = Instant::now();
+		let up = Action::Dpad(Direction::Up);
+		let input = |sequences: &mut Sequences, secs| {
+			sequences.input(&up, start + Duration::from_secs(secs))
+		};
+
+		sequences.configure(vec![sequence("up", "UUU")]);
+		assert_eq!(input(&mut sequences, 0), Progress::Partial);
+		assert_eq!(input(&mut sequences, 2), Progress::Partial);
+		assert_eq!(input(&mut sequences, 5), Progress::Partial);
+		assert_eq!(input(&mut sequences, 6), Progress::Partial);
+		assert_eq!(
+			input(&mut sequences, 8),
+			Progress::Complete(sequence("up", "UUU"))
+		);
+	}
+}
@@ -0,0 +1,287 @@
+/*
+ * Copyright 2025  Simon Arlott
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use std::time::{Duration, Instant};
+
+/// Decides when to put the display to sleep and resume it
+#[derive(Debug)]
+pub struct SleepState {
+	/// When the space became empty
+	empty_since: Option<Instant>,
+	/// When the display was last resumed or used
+	awake_since: Instant,
+	/// Presence has been reported
+	presence_known: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+	Sleep,
+	Resume,
+}
+
+/// How long to wait before sleeping
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timing {
+	/// Time that the space must be empty for
+	pub delay: Duration,
+	/// Minimum time to stay awake after resuming
+	pub awake: Duration,
+	/// Time without use before sleeping when presence is not known
+	pub idle: Option<Duration>,
+}
+
+impl SleepState {
+	/// Maximum time between checks (for schedule changes)
+	pub const POLL: Duration = Duration::from_secs(60);
+
+	pub fn new(now: Instant) -> Self {
+		Self {
+			empty_since: None,
+			awake_since: now,
+			presence_known: false,
+		}
+	}
+
+	/// The space is occupied or empty
+	pub fn presence(&mut self, present: bool, sleeping: bool, now: Instant) -> Option<Action> {
+		self.presence_known = true;
+
+		if present {
+			self.empty_since = None;
+			self.wake(sleeping, now)
+		} else {
+			self.empty_since.get_or_insert(now);
+			None
+		}
+	}
+
+	/// The display is being used
+	pub fn activity(&mut self, sleeping: bool, now: Instant) -> Option<Action> {
+		self.wake(sleeping, now)
+	}
+
+	fn wake(&mut self, sleeping: bool, now: Instant) -> Option<Action> {
+		self.awake_since = now;
+		sleeping.then_some(Action::Resume)
+	}
+
+	/// Check if the display should sleep or resume now (never sleeping
+	/// during scheduled times), and how long to wait until the next check
+	pub fn update(
+		&mut self,
+		sleeping: bool,
+		never: bool,
+		timing: Timing,
+		now: Instant,
+	) -> (Option<Action>, Duration) {
+		/* Only automatic sleep is prevented, the display can still be turned off */
+		if never {
+			return (None, Self::POLL);
+		}
+
+		let due = match self.empty_since {
+			Some(empty_since) => Some(empty_since + timing.delay),
+			None if !self.presence_known => timing.idle.map(|idle| self.awake_since + idle),
+			None => None,
+		};
+
+		match due {
+			Some(due) if !sleeping => {
+				let due = due.max(self.awake_since + timing.awake);
+
+				if now >= due {
+					(Some(Action::Sleep), Self::POLL)
+				} else {
+					(None, (due - now).min(Self::POLL))
+				}
+			}
+			_ => (None, Self::POLL),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::{Duration, Instant};
+
+	use super::{Action, SleepState, Timing};
+
+	const TIMING: Timing = Timing {
+		delay: Duration::from_secs(300),
+		awake: Duration::from_secs(600),
+		idle: None,
+	};
+
+	fn secs(secs: u64) -> Duration {
+		Duration::from_secs(secs)
+	}
+
+	#[test]
+	fn sleep_after_continuous_empty() {
+		let awake = Instant::now();
+		let start = awake + secs(3600);
+		let mut state = SleepState::new(awake);
+
+		assert_eq!(state.presence(false, false, start), None);
+		assert_eq!(
+			state.update(false, false, TIMING, start + secs(240)),
+			(None, secs(60))
+		);
+		assert_eq!(
+			state.update(false, false, TIMING, start + secs(270)),
+			(None, secs(30))
+		);
+
+		/* Someone arrives briefly */
+		assert_eq!(state.presence(true, false, start + secs(280)), None);
+		assert_eq!(state.presence(false, false, start + secs(290)), None);
+		assert_eq!(
+			state.update(false, false, TIMING, start + secs(300)),
+			(None, secs(60))
+		);
+
+		/* Still awake because of the arrival */
+		assert_eq!(
+			state.update(false, false, TIMING, start + secs(590)),
+			(None, secs(60))
+		);
+		assert_eq!(
+			state.update(false, false, TIMING, start + secs(880)),
+			(Some(Action::Sleep), secs(60))
+		);
+
+		/* Repeated empty messages don't restart the delay */
+		assert_eq!(state.presence(false, true, start + secs(900)), None);
+		assert_eq!(
+			state.update(true, false, TIMING, start + secs(900)),
+			(None, secs(60))
+		);
+	}
+
+	#[test]
+	fn stay_awake_after_resume() {
+		let start = Instant::now();
+		let mut state = SleepState::new(start);
+
+		assert_eq!(state.presence(false, false, start), None);
+		assert_eq!(
+			state.update(false, false, TIMING, start + secs(300)),
+			(None, secs(60))
+		);
+		assert_eq!(
+			state.update(false, false, TIMING, start + secs(570)),
+			(None, secs(30))
+		);
+		assert_eq!(
+			state.update(false, false, TIMING, start + secs(600)),
+			(Some(Action::Sleep), secs(60))
+		);
+
+		assert_eq!(
+			state.presence(true, true, start + secs(700)),
+			Some(Action::Resume)
+		);
+		assert_eq!(state.presence(false, false, start + secs(710)), None);
+		assert_eq!(
+			state.update(false, false, TIMING, start + secs(1290)),
+			(None, secs(10))
+		);
+		assert_eq!(
+			state.update(false, false, TIMING, start + secs(1300)),
+			(Some(Action::Sleep), secs(60))
+		);
+	}
+
+	#[test]
+	fn activity_wakes_display() {
+		let awake = Instant::now();
+		let start = awake + secs(3600);
+		let mut state = SleepState::new(awake);
+
+		state.presence(false, false, start - secs(3600));
+		assert_eq!(
+			state.update(false, false, TIMING, start),
+			(Some(Action::Sleep), secs(60))
+		);
+
+		assert_eq!(state.activity(true, start), Some(Action::Resume));
+		assert_eq!(state.activity(false, start + secs(10)), None);
+		assert_eq!(
+			state.update(false, false, TIMING, start + secs(600)),
+			(None, secs(10))
+		);
+		assert_eq!(
+			state.update(false, false, TIMING, start + secs(610)),
+			(Some(Action::Sleep), secs(60))
+		);
+	}
+
+	#[test]
+	fn never_sleep_during_schedule() {
+		let awake = Instant::now();
+		let start = awake + secs(3600);
+		let mut state = SleepState::new(awake);
+
+		state.presence(false, false, start - secs(3600));
+		assert_eq!(state.update(false, true, TIMING, start), (None, secs(60)));
+		assert_eq!(state.update(true, true, TIMING, start), (None, secs(60)));
+
+		/* Sleep when the schedule ends */
+		assert_eq!(
+			state.update(false, false, TIMING, start + secs(60)),
+			(Some(Action::Sleep), secs(60))
+		);
+	}
+
+	#[test]
+	fn idle_timeout_without_presence() {
+		let timing = Timing {
+			idle: Some(secs(900)),
+			..TIMING
+		};
+		let start = Instant::now();
+		let mut state = SleepState::new(start);
+
+		assert_eq!(state.update(false, false, timing, start), (None, secs(60)));
+		assert_eq!(
+			state.update(false, false, timing, start + secs(870)),
+			(None, secs(30))
+		);
+
+		/* Input restarts the timeout */
+		assert_eq!(state.activity(false, start + secs(880)), None);
+		assert_eq!(
+			state.update(false, false, timing, start + secs(1750)),
+			(None, secs(30))
+		);
+		assert_eq!(
+			state.update(false, false, timing, start + secs(1780)),
+			(Some(Action::Sleep), secs(60))
+		);
+
+		/* Presence replaces the timeout */
+		assert_eq!(
+			state.presence(true, true, start + secs(1800)),
+			Some(Action::Resume)
+		);
+		assert_eq!(
+			state.update(false, false, timing, start + secs(5000)),
+			(None, secs(60))
+		);
+	}
+}
@@ -0,0 +1,237 @@
+/*
+ * Copyright 2025  Simon Arlott
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use std::{process::Command, thread, time::Duration};
+
+use anyhow::{Error, anyhow};
+use evdev::{AttributeSet, EventType, InputEvent, KeyCode, uinput::VirtualDevice};
+use log::{debug, error};
+use sha2::{Digest, Sha256};
+
+/// Virtual keyboard (using uinput) that presses keys on whichever window
+/// the compositor has focused
+#[derive(derive_more::Debug)]
+pub struct Keyboard {
+	#[debug("{:?}", device.is_some())]
+	device: Option<VirtualDevice>,
+}
+
+/// Screen capture using grim
+#[derive(Debug)]
+pub struct Grim;
+
+impl Keyboard {
+	const NAME: &str = "hacklab-status-screen";
+	/* The compositor needs time to add a new device before it receives keys */
+	const STARTUP_DELAY: Duration = Duration::from_secs(1);
+	/* xdotool(1): Delay between keystrokes. Default is 12ms. */
+	const DELAY: Duration = Duration::from_millis(12);
+
+	pub fn new() -> Self {
+		Self { device: None }
+	}
+
+	/// Press keys using the same format as xdotool
+	/// (e.g. "Ctrl+Shift+Tab Down x")
+	pub fn press(&mut self, keys: &str) -> Result<(), Error> {
+		let mut presses = Vec::new();
+
+		/* Check all the keys before pressing any of them */
+		for combination in keys.split_whitespace() {
+			let mut parts: Vec<&str> = combination.split("+").collect();
+			let key = parts.pop().ok_or(anyhow!("Empty key"))?;
+			let (key, shift) = Self::key_code(key)?;
+			let mut modifiers = parts
+				.iter()
+				.map(|modifier| match *modifier {
+					"Alt" => Ok(KeyCode::KEY_LEFTALT),
+					"Ctrl" | "Control" => Ok(KeyCode::KEY_LEFTCTRL),
+					"Super" | "Meta" => Ok(KeyCode::KEY_LEFTMETA),
+					"Shift" => Ok(KeyCode::KEY_LEFTSHIFT),
+					_ => Err(anyhow!("Unknown modifier {modifier:?} in {combination:?}")),
+				})
+				.collect::<Result<Vec<_>, _>>()?;
+
+			if shift && !modifiers.contains(&KeyCode::KEY_LEFTSHIFT) {
+				modifiers.push(KeyCode::KEY_LEFTSHIFT);
+			}
+
+			presses.push((modifiers, key));
+		}
+
+		let device = self.device()?;
+
+		for (modifiers, key) in presses {
+			let event = |key: &KeyCode, value| InputEvent::new(EventType::KEY.0, key.code(), value);
+
+			let result = device
+				.emit(
+					&modifiers
+						.iter()
+						.chain([&key])
+						.map(|key| event(key, 1))
+						.collect::<Vec<_>>(),
+				)
+				.and_then(|_| {
+					device.emit(
+						&[&key]
+							.into_iter()
+							.chain(modifiers.iter().rev())
+							.map(|key| event(key, 0))
+							.collect::<Vec<_>>(),
+					)
+				});
+
+			if let Err(err) = result {
+				self.device = None;
+				return Err(err.into());
+			}
+
+			thread::sleep(Self::DELAY);
+		}
+
+		Ok(())
+	}
+
+	fn device(&mut self) -> Result<&mut VirtualDevice, Error> {
+		if self.device.is_none() {
+			let mut keys = AttributeSet::<KeyCode>::new();
+
+			for code in 1..KeyCode::KEY_MICMUTE.code() {
+				keys.insert(KeyCode::new(code));
+			}
+
+			let device = VirtualDevice::builder()?
+				.name(Self::NAME)
+				.with_keys(&keys)?
+				.build()?;
+
+			debug!("Created virtual keyboard");
+			thread::sleep(Self::STARTUP_DELAY);
+			self.device = Some(device);
+		}
+
+		Ok(self.device.as_mut().unwrap())
+	}
+
+	/// Convert an xdotool key name to a key code on a US keyboard and
+	/// whether shift is required
+	fn key_code(key: &str) -> Result<(KeyCode, bool), Error> {
+		Ok(match key {
+			"Up" => (KeyCode::KEY_UP, false),
+			"Down" => (KeyCode::KEY_DOWN, false),
+			"Left" => (KeyCode::KEY_LEFT, false),
+			"Right" => (KeyCode::KEY_RIGHT, false),
+			"Prior" | "Page_Up" => (KeyCode::KEY_PAGEUP, false),
+			"Next" | "Page_Down" => (KeyCode::KEY_PAGEDOWN, false),
+			"Home" => (KeyCode::KEY_HOME, false),
+			"End" => (KeyCode::KEY_END, false),
+			"Tab" => (KeyCode::KEY_TAB, false),
+			"Escape" => (KeyCode::KEY_ESC, false),
+			"BackSpace" => (KeyCode::KEY_BACKSPACE, false),
+			"Return" => (KeyCode::KEY_ENTER, false),
+			"space" => (KeyCode::KEY_SPACE, false),
+			"colon" => (KeyCode::KEY_SEMICOLON, true),
+			"semicolon" => (KeyCode::KEY_SEMICOLON, false),
+			"slash" => (KeyCode::KEY_SLASH, false),
+			"question" => (KeyCode::KEY_SLASH, true),
+			"period" => (KeyCode::KEY_DOT, false),
+			"comma" => (KeyCode::KEY_COMMA, false),
+			"minus" => (KeyCode::KEY_MINUS, false),
+			"underscore" => (KeyCode::KEY_MINUS, true),
+			"equal" => (KeyCode::KEY_EQUAL, false),
+			"plus" => (KeyCode::KEY_EQUAL, true),
+			"asciitilde" => (KeyCode::KEY_GRAVE, true),
+			"exclam" => (KeyCode::KEY_1, true),
+			"at" => (KeyCode::KEY_2, true),
+			"numbersign" => (KeyCode::KEY_3, true),
+			"percent" => (KeyCode::KEY_5, true),
+			"ampersand" => (KeyCode::KEY_7, true),
+			_ => {
+				let mut chars = key.chars();
+
+				match (chars.next(), chars.next()) {
+					(Some(c), None) if c.is_ascii_alphanumeric() => (
+						format!("KEY_{}", c.to_ascii_uppercase())
+							.parse()
+							.map_err(|_| anyhow!("Unknown key {key:?}"))?,
+						c.is_ascii_uppercase(),
+					),
+					_ => return Err(anyhow!("Unknown key {key:?}")),
+				}
+			}
+		})
+	}
+}
+
+impl Grim {
+	pub fn see(&self) -> Option<String> {
+		match Command::new("grim").args(["-t", "ppm", "-"]).output() {
+			Ok(output) if output.status.success() => {
+				let mut hasher = Sha256::new();
+				hasher.update(&output.stdout);
+				let hash = hasher.finalize();
+				Some(format!("{:x}", hash))
+			}
+			Ok(output) => {
+				error!(
+					"Unable to capture image: {}",
+					String::from_utf8_lossy(&output.stderr).trim()
+				);
+				None
+			}
+			Err(err) => {
+				error!("Unable to capture image: {err}");
+				None
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use evdev::KeyCode;
+
+	use super::Keyboard;
+
+	#[test]
+	fn key_codes() {
+		assert_eq!(Keyboard::key_code("x").unwrap(), (KeyCode::KEY_X, false));
+		assert_eq!(Keyboard::key_code("X").unwrap(), (KeyCode::KEY_X, true));
+		assert_eq!(Keyboard::key_code("9").unwrap(), (KeyCode::KEY_9, false));
+		assert_eq!(
+			Keyboard::key_code("colon").unwrap(),
+			(KeyCode::KEY_SEMICOLON, true)
+		);
+		assert_eq!(
+			Keyboard::key_code("Prior").unwrap(),
+			(KeyCode::KEY_PAGEUP, false)
+		);
+		assert!(Keyboard::key_code("U00E9").is_err());
+		assert!(Keyboard::key_code("").is_err());
+	}
+
+	#[test]
+	fn invalid_keys_are_not_pressed() {
+		let mut keyboard = Keyboard::new();
+
+		/* Fails before trying to create the device */
+		assert!(keyboard.press("Ctrl+t Hyper+x").is_err());
+		assert!(keyboard.press("a U00E9").is_err());
+		assert!(keyboard.device.is_none());
+	}
+}